@@ -0,0 +1,178 @@
+use blobber::level::Level;
+use blobber::script::Script;
+use blobber::simulation::{blob::BlobId, Event, Simulation};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const USAGE: &str =
+    "usage: blobber-headless [--level <file>] [--steps <count>] [--print-steps] [--deterministic] \
+     [<script>]";
+
+struct Options {
+    script: Option<String>,
+    // Played instead of the default arena.
+    level: Option<String>,
+    steps: Option<u64>,
+    print_steps: bool,
+    deterministic: bool,
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut options = Options {
+        script: None,
+        level: None,
+        steps: None,
+        print_steps: false,
+        deterministic: false,
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--steps" => {
+                let steps = args.next().ok_or("--steps needs a value")?;
+                options.steps = Some(
+                    steps
+                        .parse()
+                        .map_err(|_| format!("invalid step count '{}'", steps))?,
+                );
+            }
+            "--level" => options.level = Some(args.next().ok_or("--level needs a file")?),
+            "--print-steps" => options.print_steps = true,
+            "--deterministic" => options.deterministic = true,
+            "--help" | "-h" => return Err(USAGE.to_string()),
+            _ if arg.starts_with("--") => return Err(format!("unknown option '{}'", arg)),
+            _ if options.script.is_none() => options.script = Some(arg),
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+
+    Ok(options)
+}
+
+#[derive(Default)]
+struct BlobStats {
+    distance: f32,
+    max_speed: f32,
+    collisions: u32,
+    wall_hits: u32,
+    food: u32,
+}
+
+fn main() {
+    let options = parse_options().unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        std::process::exit(2);
+    });
+
+    let script = match &options.script {
+        Some(path) => {
+            let source = std::fs::read_to_string(path).unwrap_or_else(|error| {
+                eprintln!("{}: {}", path, error);
+                std::process::exit(1);
+            });
+            Script::parse(&source).unwrap_or_else(|error| {
+                eprintln!("{}: {}", path, error);
+                std::process::exit(1);
+            })
+        }
+        None => Script::default(),
+    };
+    let steps = options
+        .steps
+        .or_else(|| script.last_step().map(|step| step + 1))
+        .unwrap_or(600);
+
+    // Same fixed step as the windowed game.
+    let dt = Duration::from_micros(16_667).as_secs_f32();
+    let mut simulation = match &options.level {
+        Some(path) => Level::load(Path::new(path))
+            .unwrap_or_else(|error| {
+                eprintln!("{}: {}", path, error);
+                std::process::exit(1);
+            })
+            .simulation(),
+        None => Simulation::new(),
+    };
+    simulation.set_deterministic(options.deterministic);
+    let mut stats = HashMap::<BlobId, BlobStats>::new();
+    let mut kills = 0;
+    let started = Instant::now();
+
+    for step in 0..steps {
+        let events = simulation.simulate(&script.inputs(step), dt);
+
+        for event in &events {
+            match event {
+                Event::Collision((first, second, _, _)) => {
+                    stats.entry(*first).or_default().collisions += 1;
+                    stats.entry(*second).or_default().collisions += 1;
+                }
+                Event::WallHit((blob_id, _)) => {
+                    stats.entry(*blob_id).or_default().wall_hits += 1;
+                }
+                Event::Eat((blob_id, count)) => {
+                    stats.entry(*blob_id).or_default().food += count;
+                }
+                Event::Kill(_) => kills += 1,
+                _ => (),
+            }
+        }
+
+        for blob in simulation.blobs() {
+            let blob_stats = stats.entry(blob.id).or_default();
+            blob_stats.distance += (blob.circle.pos - blob.previous_pos).magnitude();
+            blob_stats.max_speed = blob_stats.max_speed.max(blob.velocity.magnitude());
+
+            if options.print_steps {
+                println!(
+                    "{} {} {:.3} {:.3} {:.3} {:.3} {:.3}",
+                    step,
+                    blob.id,
+                    blob.circle.pos.x,
+                    blob.circle.pos.y,
+                    blob.velocity.x,
+                    blob.velocity.y,
+                    blob.rotation
+                );
+            }
+        }
+
+        if options.print_steps && options.deterministic {
+            println!("{} hash {:016x}", step, simulation.state_hash());
+        }
+    }
+
+    let elapsed = started.elapsed();
+    println!(
+        "{} steps ({:.1} s simulated) in {:.3} s",
+        steps,
+        steps as f32 * dt,
+        elapsed.as_secs_f32()
+    );
+    if options.deterministic {
+        println!("state hash {:016x}", simulation.state_hash());
+    }
+
+    if kills > 0 {
+        println!("{} blobs killed", kills);
+    }
+    let mut blobs: Vec<_> = simulation.blobs().iter().collect();
+    blobs.sort_by_key(|blob| blob.id);
+    for blob in blobs {
+        let blob_stats = stats.entry(blob.id).or_default();
+        println!(
+            "blob {}: pos ({:.2}, {:.2}) health {:.1} distance {:.2} max speed {:.2} collisions {} wall hits {} food {}",
+            blob.id,
+            blob.circle.pos.x,
+            blob.circle.pos.y,
+            blob.health,
+            blob_stats.distance,
+            blob_stats.max_speed,
+            blob_stats.collisions,
+            blob_stats.wall_hits,
+            blob_stats.food
+        );
+    }
+}
@@ -0,0 +1,54 @@
+use blobber::math::point::PointF32;
+use blobber::math::rect::Rect;
+use blobber::net::server::Server;
+use blobber::simulation::Simulation;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "usage: blobber-server [--bind <address>]";
+
+fn main() {
+    let mut bind = "0.0.0.0:7878".to_string();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match (arg.as_str(), args.next()) {
+            ("--bind", Some(address)) => bind = address,
+            _ => {
+                eprintln!("{}", USAGE);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    let arena = Rect::new(
+        PointF32::new(-1000.0, -1000.0),
+        PointF32::new(1000.0, 1000.0),
+    );
+    let mut server = Server::bind(&bind, Simulation::new_empty(arena)).unwrap_or_else(|error| {
+        eprintln!("{}: {}", bind, error);
+        std::process::exit(1);
+    });
+    println!("listening on {}", server.local_addr().unwrap());
+
+    // Same fixed step as the game, without catching up when late.
+    let dt = Duration::from_micros(16_667);
+    let mut next_tick = Instant::now();
+    let mut client_count = 0;
+    loop {
+        server.tick(dt.as_secs_f32());
+        if let Some(error) = server.take_send_error() {
+            eprintln!("sending state: {}", error);
+        }
+        if server.client_count() != client_count {
+            client_count = server.client_count();
+            println!("{} clients", client_count);
+        }
+
+        next_tick += dt;
+        let now = Instant::now();
+        if next_tick > now {
+            std::thread::sleep(next_tick - now);
+        } else {
+            next_tick = now;
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use crate::math::point::PointF32;
+use crate::math::rect::Rect;
+use crate::simulation::{blob::OwnerId, Simulation};
+
+pub struct Camera {
+    // Screen area the camera draws to, in pixels.
+    viewport: Rect,
+    center: PointF32,
+    zoom: f32,
+    target_center: PointF32,
+    target_zoom: f32,
+    // Zoom set with the mouse wheel, on top of the zoom needed to frame the
+    // followed blobs.
+    user_zoom: f32,
+    // Owners whose blobs are kept in view.
+    following: Vec<OwnerId>,
+}
+
+impl Camera {
+    // Space kept around the followed blobs, in world units.
+    const MARGIN: f32 = 150.0;
+    const MIN_ZOOM: f32 = 0.1;
+    const MAX_ZOOM: f32 = 4.0;
+    // How fast the camera catches up with its target, higher is faster.
+    const EASING: f32 = 5.0;
+
+    pub fn new(viewport: Rect) -> Self {
+        Camera {
+            viewport,
+            center: PointF32::new(0.0, 0.0),
+            zoom: 1.0,
+            target_center: PointF32::new(0.0, 0.0),
+            target_zoom: 1.0,
+            user_zoom: 1.0,
+            following: Vec::new(),
+        }
+    }
+
+    pub fn follow(&mut self, owners: &[OwnerId]) {
+        self.following = owners.to_vec();
+    }
+
+    pub fn set_viewport(&mut self, viewport: Rect) {
+        self.viewport = viewport;
+    }
+
+    pub fn viewport(&self) -> Rect {
+        self.viewport
+    }
+
+    pub fn center(&self) -> PointF32 {
+        self.center
+    }
+
+    pub fn zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    pub fn set_zoom(&mut self, zoom: f32) {
+        self.zoom = zoom;
+    }
+
+    pub fn target_zoom(&self) -> f32 {
+        self.target_zoom
+    }
+
+    pub fn look_at(&mut self, center: PointF32) {
+        self.center = center;
+        self.target_center = center;
+    }
+
+    pub fn zoom_by(&mut self, factor: f32, screen_pos: PointF32) {
+        self.user_zoom = (self.user_zoom * factor).clamp(Camera::MIN_ZOOM, Camera::MAX_ZOOM);
+
+        if self.following.is_empty() {
+            // Keep the world position under the cursor where it is.
+            let world_pos = self.screen_to_world(screen_pos);
+            self.zoom = self.user_zoom;
+            self.target_zoom = self.user_zoom;
+            self.center = world_pos - (screen_pos - self.viewport.center()) / self.zoom;
+            self.target_center = self.center;
+        }
+    }
+
+    pub fn update(&mut self, simulation: &Simulation, timepoint: f32, dt: f32) {
+        let mut bounds = Rect::new_empty();
+        let followed = simulation
+            .blobs()
+            .iter()
+            .filter(|blob| self.following.contains(&blob.owner));
+        for blob in followed {
+            let pos = blob.interpolated_pos(timepoint);
+            let radius = blob.circle.radius;
+            bounds.include(&Rect::new(
+                pos - PointF32::new(radius, radius),
+                pos + PointF32::new(radius, radius),
+            ));
+        }
+
+        if bounds.bottom_left.x <= bounds.top_right.x {
+            bounds.grow(Camera::MARGIN, Camera::MARGIN);
+            let bounds_size = bounds.size();
+            let viewport_size = self.viewport.size();
+            let fit_zoom = f32::min(
+                viewport_size.x / bounds_size.x,
+                viewport_size.y / bounds_size.y,
+            )
+            .min(1.0);
+
+            self.target_center = bounds.center();
+            self.target_zoom =
+                (fit_zoom * self.user_zoom).clamp(Camera::MIN_ZOOM, Camera::MAX_ZOOM);
+        }
+
+        let t = 1.0 - (-Camera::EASING * dt).exp();
+        self.center += (self.target_center - self.center) * t;
+        self.zoom += (self.target_zoom - self.zoom) * t;
+    }
+
+    pub fn world_to_screen(&self, pos: PointF32) -> PointF32 {
+        (pos - self.center) * self.zoom + self.viewport.center()
+    }
+
+    pub fn screen_to_world(&self, pos: PointF32) -> PointF32 {
+        (pos - self.viewport.center()) / self.zoom + self.center
+    }
+
+    pub fn visible_rect(&self) -> Rect {
+        Rect::new_valid(
+            self.screen_to_world(self.viewport.bottom_left),
+            self.screen_to_world(self.viewport.top_right),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::blob::BlobDesc;
+
+    fn assert_close(p1: PointF32, p2: PointF32) {
+        assert!((p1 - p2).magnitude() < 0.001, "{:?} != {:?}", p1, p2);
+    }
+
+    #[test]
+    fn test_transforms() {
+        let mut camera = Camera::new(Rect::new(
+            PointF32::new(0.0, 0.0),
+            PointF32::new(1000.0, 600.0),
+        ));
+        camera.look_at(PointF32::new(100.0, -50.0));
+
+        assert_close(
+            camera.world_to_screen(PointF32::new(100.0, -50.0)),
+            PointF32::new(500.0, 300.0),
+        );
+        assert_close(
+            camera.screen_to_world(PointF32::new(0.0, 0.0)),
+            PointF32::new(-400.0, -350.0),
+        );
+
+        camera.zoom_by(2.0, PointF32::new(500.0, 300.0));
+        assert_close(
+            camera.world_to_screen(PointF32::new(110.0, -50.0)),
+            PointF32::new(520.0, 300.0),
+        );
+
+        let pos = PointF32::new(-13.0, 77.0);
+        assert_close(camera.screen_to_world(camera.world_to_screen(pos)), pos);
+
+        let visible = camera.visible_rect();
+        assert_close(visible.bottom_left, PointF32::new(-150.0, -200.0));
+        assert_close(visible.top_right, PointF32::new(350.0, 100.0));
+    }
+
+    #[test]
+    fn test_zoom_keeps_cursor_position() {
+        let mut camera = Camera::new(Rect::new(
+            PointF32::new(0.0, 0.0),
+            PointF32::new(1000.0, 600.0),
+        ));
+        let cursor = PointF32::new(800.0, 100.0);
+        let world_pos = camera.screen_to_world(cursor);
+
+        camera.zoom_by(1.5, cursor);
+        assert_close(camera.screen_to_world(cursor), world_pos);
+    }
+
+    #[test]
+    fn test_follow_frames_blobs() {
+        let mut simulation = Simulation::new_empty(Rect::new(
+            PointF32::new(-1000.0, -1000.0),
+            PointF32::new(1000.0, 1000.0),
+        ));
+        let first = simulation.spawn_blob(&BlobDesc::new(-900.0, 0.0, 10.0));
+        let second = simulation.spawn_blob(&BlobDesc::new(900.0, 0.0, 10.0));
+
+        let mut camera = Camera::new(Rect::new(
+            PointF32::new(0.0, 0.0),
+            PointF32::new(1000.0, 600.0),
+        ));
+        camera.follow(&[first, second]);
+        for _ in 0..200 {
+            camera.update(&simulation, 1.0, 0.1);
+        }
+
+        let visible = camera.visible_rect();
+        assert_close(camera.center(), PointF32::new(0.0, 0.0));
+        assert!(visible.bottom_left.x <= -910.0 && visible.top_right.x >= 910.0);
+        assert!(camera.zoom() < 1.0);
+    }
+}
@@ -1,7 +1,13 @@
+use crate::camera::Camera;
+use crate::input::InputState;
+use crate::keymap::KeyMap;
+use crate::math::point::PointF32;
 use crate::math::rect::Rect;
-use crate::simulation::{blob::BlobId, InputEvent, Simulation};
+use crate::simulation::food::FOOD_RADIUS;
+use crate::simulation::projectile::PROJECTILE_RADIUS;
+use crate::simulation::{blob::OwnerId, Action, Simulation};
 use femtovg;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 
 pub struct Renderer {}
 
@@ -13,104 +19,357 @@ impl Renderer {
     pub fn render<T: femtovg::Renderer>(
         &self,
         simulation: &Simulation,
+        camera: &Camera,
         timepoint: f32,
         canvas: &mut femtovg::Canvas<T>,
     ) {
-        let objects = simulation.objects(Rect::new_empty());
+        let viewport = camera.viewport();
+        let viewport_size = viewport.size();
+        let viewport_center = viewport.center();
+        let center = camera.center();
+        canvas.save();
+        canvas.scissor(
+            viewport.bottom_left.x,
+            viewport.bottom_left.y,
+            viewport_size.x,
+            viewport_size.y,
+        );
+        canvas.translate(viewport_center.x, viewport_center.y);
+        canvas.scale(camera.zoom(), camera.zoom());
+        canvas.translate(-center.x, -center.y);
+
+        let arena = simulation.rect();
+        let arena_size = arena.size();
+        let mut path = femtovg::Path::new();
+        path.rect(
+            arena.bottom_left.x,
+            arena.bottom_left.y,
+            arena_size.x,
+            arena_size.y,
+        );
+        let mut paint = femtovg::Paint::color(femtovg::Color::rgba(0, 0, 255, 128));
+        paint.set_line_width(4.0);
+        canvas.stroke_path(&mut path, paint);
+
+        let mut path = femtovg::Path::new();
+        for obstacle in simulation.obstacles() {
+            let size = obstacle.size();
+            path.rect(
+                obstacle.bottom_left.x,
+                obstacle.bottom_left.y,
+                size.x,
+                size.y,
+            );
+        }
+        canvas.fill_path(
+            &mut path,
+            femtovg::Paint::color(femtovg::Color::rgba(90, 90, 90, 255)),
+        );
+
+        let mut path = femtovg::Path::new();
+        for food in simulation.food(camera.visible_rect()) {
+            path.circle(food.pos.x, food.pos.y, FOOD_RADIUS);
+        }
+        canvas.fill_path(
+            &mut path,
+            femtovg::Paint::color(femtovg::Color::rgba(0, 160, 0, 160)),
+        );
+
+        // 'timepoint' is how far we are between the previous and the current
+        // simulation step, so objects are drawn in between the two.
+        let objects = simulation.objects(camera.visible_rect());
         for object in objects {
+            let pos = object.interpolated_pos(timepoint);
+            let rotation = object.interpolated_rotation(timepoint);
+            // Blobs turn red as they lose health.
+            let damage = 1.0 - (object.health / object.max_health).clamp(0.0, 1.0);
             let mut path = femtovg::Path::new();
-            path.circle(
-                object.circle.pos.x,
-                object.circle.pos.y,
-                object.circle.radius,
-            );
+            path.circle(pos.x, pos.y, object.circle.radius);
             canvas.fill_path(
                 &mut path,
-                femtovg::Paint::color(femtovg::Color::rgba(0, 0, 0, 128)),
+                femtovg::Paint::color(femtovg::Color::rgba((damage * 255.0) as u8, 0, 0, 128)),
             );
             path = femtovg::Path::new();
-            path.move_to(object.circle.pos.x, object.circle.pos.y);
-            path.line_to(
-                object.circle.pos.x + object.rotation.cos() * 12.0,
-                object.circle.pos.y + object.rotation.sin() * 12.0,
-            );
+            path.move_to(pos.x, pos.y);
+            path.line_to(pos.x + rotation.cos() * 12.0, pos.y + rotation.sin() * 12.0);
             let mut paint = femtovg::Paint::color(femtovg::Color::rgba(255, 0, 0, 128));
             paint.set_line_width(5.0);
             canvas.stroke_path(&mut path, paint);
         }
+
+        let mut path = femtovg::Path::new();
+        for projectile in simulation.projectiles() {
+            let pos = projectile.interpolated_pos(timepoint);
+            path.circle(pos.x, pos.y, PROJECTILE_RADIUS);
+        }
+        canvas.fill_path(
+            &mut path,
+            femtovg::Paint::color(femtovg::Color::rgba(255, 160, 0, 255)),
+        );
+        canvas.restore();
+    }
+
+    pub fn render_divider<T: femtovg::Renderer>(
+        &self,
+        start: PointF32,
+        end: PointF32,
+        canvas: &mut femtovg::Canvas<T>,
+    ) {
+        let mut path = femtovg::Path::new();
+        path.move_to(start.x, start.y);
+        path.line_to(end.x, end.y);
+        let mut paint = femtovg::Paint::color(femtovg::Color::rgba(0, 0, 0, 255));
+        paint.set_line_width(2.0);
+        canvas.stroke_path(&mut path, paint);
+    }
+}
+
+impl Default for Renderer {
+    fn default() -> Self {
+        Renderer::new()
     }
 }
 
 pub struct Game {
     pub simulation: Simulation,
     pub renderer: Renderer,
-    keymap: HashMap<winit::event::VirtualKeyCode, (BlobId, InputEvent)>,
+    pub camera: Camera,
+    // One camera per player, used when the screen is split.
+    player_cameras: Vec<Camera>,
+    split_screen: bool,
+    split: bool,
+    pub input: InputState,
+    keymap: KeyMap,
 }
 
 impl Game {
-    fn add_keys(
-        keymap: &mut HashMap<winit::event::VirtualKeyCode, (BlobId, InputEvent)>,
-        blob_id: BlobId,
-        forward: winit::event::VirtualKeyCode,
-        backward: winit::event::VirtualKeyCode,
-        left: winit::event::VirtualKeyCode,
-        right: winit::event::VirtualKeyCode,
-    ) {
-        keymap.insert(forward, (blob_id, InputEvent::Forward));
-        keymap.insert(backward, (blob_id, InputEvent::Backward));
-        keymap.insert(left, (blob_id, InputEvent::TurnLeft));
-        keymap.insert(right, (blob_id, InputEvent::TurnRight));
-    }
-
-    pub fn new() -> Self {
-        use winit::event::VirtualKeyCode::*;
-        let mut keymap = HashMap::<winit::event::VirtualKeyCode, (BlobId, InputEvent)>::new();
-
-        Game::add_keys(&mut keymap, 0, Up, Down, Left, Right);
-        Game::add_keys(&mut keymap, 1, W, S, A, D);
+    pub fn new(keymap: KeyMap) -> Self {
+        let players: [OwnerId; 2] = [0, 1];
+        let viewport = Rect::new(PointF32::new(0.0, 0.0), PointF32::new(1000.0, 600.0));
+        let mut camera = Camera::new(viewport);
+        camera.follow(&players);
+        let player_cameras = players
+            .iter()
+            .map(|owner| {
+                let mut camera = Camera::new(viewport);
+                camera.follow(&[*owner]);
+                camera
+            })
+            .collect();
 
         return Game {
             simulation: Simulation::new(),
             renderer: Renderer::new(),
+            camera,
+            player_cameras,
+            split_screen: false,
+            split: false,
+            input: InputState::new(),
             keymap,
         };
     }
 
-    pub fn handle_inputs(
-        &self,
-        keys_down: &HashSet<winit::event::VirtualKeyCode>,
-    ) -> HashMap<BlobId, Vec<InputEvent>> {
-        // TODO This function is not really needed, instead an 'queue_input' or something
-        // can be added, that should be called after each input.
-        let mut input_events = HashMap::<BlobId, Vec<InputEvent>>::new();
-
-        // This only works if each input key maps to one InputEvent, this might
-        // not be the case (e.g. Forward + Ctrl should only lead to
-        // InputEvent::FullThrottleForward and not one InputEvent::Forward and
-        // InputEvent::FullThrottle/InputEvent::FullThrottleForward).
-        // Then we first have to check all combined keys, remove them from vector, or something.
-        for key in keys_down {
-            match self.keymap.get(&key) {
-                Some((blob_id, mut event)) => input_events
-                    .entry(*blob_id)
-                    .or_insert(Vec::new())
-                    .push(event),
-                None => (),
+    pub fn handle_inputs(&mut self) -> HashMap<OwnerId, Vec<Action>> {
+        let actions = self.input.actions(&self.keymap);
+        self.input.end_tick();
+        actions
+    }
+
+    pub fn zoom(&mut self, factor: f32, cursor_pos: PointF32) {
+        self.camera.zoom_by(factor, cursor_pos);
+        for camera in &mut self.player_cameras {
+            camera.zoom_by(factor, cursor_pos);
+        }
+    }
+
+    pub fn toggle_split_screen(&mut self) {
+        self.split_screen = !self.split_screen;
+    }
+
+    fn split_viewports(size: PointF32, count: usize) -> Vec<Rect> {
+        // Wide windows are split side by side, tall ones top to bottom.
+        let count_f32 = count as f32;
+        (0..count)
+            .map(|i| {
+                let i_f32 = i as f32;
+                if size.x >= size.y {
+                    Rect::new(
+                        PointF32::new(size.x * i_f32 / count_f32, 0.0),
+                        PointF32::new(size.x * (i_f32 + 1.0) / count_f32, size.y),
+                    )
+                } else {
+                    Rect::new(
+                        PointF32::new(0.0, size.y * i_f32 / count_f32),
+                        PointF32::new(size.x, size.y * (i_f32 + 1.0) / count_f32),
+                    )
+                }
+            })
+            .collect()
+    }
+
+    fn update_split(&mut self, size: PointF32, timepoint: f32, frame_time: f32) {
+        // Zoom of the shared camera compared to the player cameras, below
+        // 'SPLIT_RATIO' the players are too far apart to share a view and
+        // above 'MERGE_RATIO' they are close enough to be merged again.
+        const SPLIT_RATIO: f32 = 0.6;
+        const MERGE_RATIO: f32 = 0.8;
+
+        if !self.split_screen {
+            self.split = false;
+            return;
+        }
+
+        let viewports = Game::split_viewports(size, self.player_cameras.len());
+        for (camera, viewport) in self.player_cameras.iter_mut().zip(viewports) {
+            camera.set_viewport(viewport);
+            camera.update(&self.simulation, timepoint, frame_time);
+        }
+
+        let player_zoom = self
+            .player_cameras
+            .iter()
+            .map(|camera| camera.target_zoom())
+            .fold(f32::MAX, f32::min);
+        let ratio = self.camera.target_zoom() / player_zoom;
+
+        if !self.split && ratio < SPLIT_RATIO {
+            // The player cameras start from the shared view and ease
+            // towards their own blob, so the split doesn't jump.
+            for camera in &mut self.player_cameras {
+                camera.look_at(self.camera.center());
+                camera.set_zoom(self.camera.zoom());
             }
+            self.split = true;
+        } else if self.split && ratio > MERGE_RATIO {
+            // The other way around, the shared camera starts from between
+            // the player views and eases towards both blobs.
+            let count = self.player_cameras.len() as f32;
+            let center = self
+                .player_cameras
+                .iter()
+                .fold(PointF32::new(0.0, 0.0), |sum, camera| sum + camera.center())
+                / count;
+            let zoom = self
+                .player_cameras
+                .iter()
+                .map(|camera| camera.zoom())
+                .fold(f32::MAX, f32::min);
+            self.camera.look_at(center);
+            self.camera.set_zoom(zoom);
+            self.split = false;
         }
+    }
+
+    fn update_cameras(&mut self, size: PointF32, timepoint: f32, frame_time: f32) {
+        self.camera
+            .set_viewport(Rect::new(PointF32::new(0.0, 0.0), size));
+        self.camera.update(&self.simulation, timepoint, frame_time);
+        self.update_split(size, timepoint, frame_time);
+    }
+
+    pub fn render<T: femtovg::Renderer>(
+        &mut self,
+        timepoint: f32,
+        frame_time: f32,
+        canvas: &mut femtovg::Canvas<T>,
+    ) {
+        let size = PointF32::new(canvas.width(), canvas.height());
+        self.update_cameras(size, timepoint, frame_time);
 
-        // We remove duplicates.
-        // NOTE Could be done here, but maybe instead when the simulation
-        // receives the events?
-        for (_, events) in &mut input_events {
-            events.sort();
-            events.dedup();
+        if self.split {
+            for camera in &self.player_cameras {
+                self.renderer
+                    .render(&self.simulation, camera, timepoint, canvas);
+            }
+            for camera in self.player_cameras.iter().skip(1) {
+                let viewport = camera.viewport();
+                let end = if size.x >= size.y {
+                    viewport.top_left()
+                } else {
+                    viewport.bottom_right()
+                };
+                self.renderer
+                    .render_divider(viewport.bottom_left, end, canvas);
+            }
+        } else {
+            self.renderer
+                .render(&self.simulation, &self.camera, timepoint, canvas);
         }
+    }
+}
 
-        input_events
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keymap::default_keymap;
+    use crate::simulation::blob::BlobDesc;
+
+    fn rect(left: f32, bottom: f32, right: f32, top: f32) -> Rect {
+        Rect::new(PointF32::new(left, bottom), PointF32::new(right, top))
+    }
+
+    fn blobs_at(x: f32) -> Simulation {
+        let mut simulation = Simulation::new_empty(rect(-1000.0, -1000.0, 1000.0, 1000.0));
+        simulation.spawn_blob(&BlobDesc::new(-x, 0.0, 10.0));
+        simulation.spawn_blob(&BlobDesc::new(x, 0.0, 10.0));
+        simulation
     }
 
-    pub fn render<T: femtovg::Renderer>(&self, timepoint: f32, canvas: &mut femtovg::Canvas<T>) {
-        self.renderer.render(&self.simulation, timepoint, canvas);
+    #[test]
+    fn test_split_viewports() {
+        let wide = Game::split_viewports(PointF32::new(1000.0, 600.0), 2);
+        assert_eq!(
+            wide,
+            vec![
+                rect(0.0, 0.0, 500.0, 600.0),
+                rect(500.0, 0.0, 1000.0, 600.0)
+            ]
+        );
+
+        let tall = Game::split_viewports(PointF32::new(600.0, 900.0), 3);
+        assert_eq!(
+            tall,
+            vec![
+                rect(0.0, 0.0, 600.0, 300.0),
+                rect(0.0, 300.0, 600.0, 600.0),
+                rect(0.0, 600.0, 600.0, 900.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_and_merge() {
+        let size = PointF32::new(1000.0, 600.0);
+        let mut game = Game::new(default_keymap());
+        game.simulation = blobs_at(100.0);
+        game.toggle_split_screen();
+
+        // Close blobs share the screen, far apart ones get a view each.
+        for _ in 0..50 {
+            game.update_cameras(size, 1.0, 0.1);
+        }
+        assert!(!game.split);
+        game.simulation = blobs_at(900.0);
+        game.update_cameras(size, 1.0, 0.1);
+        assert!(game.split);
+
+        // Once the blobs are back together the shared camera takes over
+        // from between the player views.
+        for _ in 0..50 {
+            game.update_cameras(size, 1.0, 0.1);
+        }
+        game.simulation = blobs_at(100.0);
+        game.camera.update(&game.simulation, 1.0, 0.1);
+        game.update_split(size, 1.0, 0.1);
+        assert!(!game.split);
+        let between = (game.player_cameras[0].center() + game.player_cameras[1].center()) / 2.0;
+        assert!((game.camera.center() - between).magnitude() < 0.001);
+        assert_eq!(game.camera.zoom(), game.player_cameras[0].zoom());
+
+        // Turned off the screen is never split.
+        game.simulation = blobs_at(900.0);
+        game.toggle_split_screen();
+        game.update_cameras(size, 1.0, 0.1);
+        assert!(!game.split);
     }
 }
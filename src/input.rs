@@ -0,0 +1,181 @@
+use crate::keymap::{KeyMap, Trigger};
+use crate::simulation::{blob::OwnerId, Action};
+use std::collections::{HashMap, HashSet};
+use winit::event::VirtualKeyCode;
+
+// Keeps track of the keyboard between two simulation ticks; which keys are
+// held and which were pressed or released since the last tick.
+pub struct InputState {
+    held: HashSet<VirtualKeyCode>,
+    pressed: HashSet<VirtualKeyCode>,
+    released: HashSet<VirtualKeyCode>,
+}
+
+impl InputState {
+    pub fn new() -> Self {
+        InputState {
+            held: HashSet::new(),
+            pressed: HashSet::new(),
+            released: HashSet::new(),
+        }
+    }
+
+    // Returns true if the key was not already held, i.e. key repeats are
+    // not counted as new presses.
+    pub fn key_down(&mut self, key: VirtualKeyCode) -> bool {
+        if self.held.insert(key) {
+            self.pressed.insert(key);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn key_up(&mut self, key: VirtualKeyCode) {
+        if self.held.remove(&key) {
+            self.released.insert(key);
+        }
+    }
+
+    pub fn is_held(&self, key: VirtualKeyCode) -> bool {
+        self.held.contains(&key)
+    }
+
+    pub fn was_pressed(&self, key: VirtualKeyCode) -> bool {
+        self.pressed.contains(&key)
+    }
+
+    pub fn was_released(&self, key: VirtualKeyCode) -> bool {
+        self.released.contains(&key)
+    }
+
+    // Should be called after each simulation tick, so edges are only seen
+    // by one tick.
+    pub fn end_tick(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+    }
+
+    pub fn actions(&self, keymap: &KeyMap) -> HashMap<OwnerId, Vec<Action>> {
+        let mut actions = HashMap::<OwnerId, Vec<Action>>::new();
+
+        // Chords are checked first, the keys of a chord that is active are
+        // then used up so e.g. Forward + Ctrl doesn't also give a Forward.
+        let mut bindings: Vec<_> = keymap.iter().collect();
+        bindings.sort_by_key(|binding| std::cmp::Reverse(binding.keys.len()));
+
+        let mut used = HashSet::<VirtualKeyCode>::new();
+        for binding in bindings {
+            // A key pressed and released within the same tick still counts.
+            let down = |key: &VirtualKeyCode| self.is_held(*key) || self.was_pressed(*key);
+            if !binding.keys.iter().all(down) || binding.keys.iter().any(|key| used.contains(key)) {
+                continue;
+            }
+
+            let active = match binding.trigger {
+                Trigger::Held => true,
+                Trigger::Pressed => binding.keys.iter().any(|key| self.was_pressed(*key)),
+            };
+            if active {
+                used.extend(binding.keys.iter().copied());
+                actions
+                    .entry(binding.owner)
+                    .or_default()
+                    .push(Action::new(binding.event, binding.value));
+            }
+        }
+
+        // Several bindings might give the same event, the strongest one is
+        // kept.
+        for owner_actions in actions.values_mut() {
+            owner_actions.sort_by(|a, b| a.event.cmp(&b.event).then(b.value.total_cmp(&a.value)));
+            owner_actions.dedup_by(|a, b| a.event == b.event);
+        }
+
+        actions
+    }
+}
+
+impl Default for InputState {
+    fn default() -> Self {
+        InputState::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keymap::{default_keymap, Binding};
+    use crate::simulation::InputEvent;
+    use VirtualKeyCode::*;
+
+    #[test]
+    fn test_edges() {
+        let mut input = InputState::new();
+
+        assert!(input.key_down(A));
+        assert!(!input.key_down(A));
+        assert!(input.is_held(A));
+        assert!(input.was_pressed(A));
+
+        input.end_tick();
+        assert!(input.is_held(A));
+        assert!(!input.was_pressed(A));
+
+        input.key_up(A);
+        assert!(!input.is_held(A));
+        assert!(input.was_released(A));
+
+        input.end_tick();
+        assert!(!input.was_released(A));
+    }
+
+    #[test]
+    fn test_chords_before_single_keys() {
+        let keymap = default_keymap();
+        let mut input = InputState::new();
+
+        input.key_down(Up);
+        input.key_down(Left);
+        assert_eq!(
+            input.actions(&keymap)[&0],
+            vec![
+                Action::new(InputEvent::Forward, 1.0),
+                Action::new(InputEvent::TurnLeft, 1.0),
+            ]
+        );
+
+        input.key_down(RControl);
+        assert_eq!(
+            input.actions(&keymap)[&0],
+            vec![
+                Action::new(InputEvent::Forward, 2.0),
+                Action::new(InputEvent::TurnLeft, 1.0),
+            ]
+        );
+        assert!(!input.actions(&keymap).contains_key(&1));
+    }
+
+    #[test]
+    fn test_pressed_trigger() {
+        let mut binding = Binding::new(Space, 3, InputEvent::Forward);
+        binding.trigger = Trigger::Pressed;
+        let keymap = vec![binding];
+        let mut input = InputState::new();
+
+        input.key_down(Space);
+        assert_eq!(
+            input.actions(&keymap)[&3],
+            vec![Action::new(InputEvent::Forward, 1.0)]
+        );
+
+        input.end_tick();
+        assert!(input.actions(&keymap).is_empty());
+
+        // Tapped between two ticks.
+        input.key_up(Space);
+        input.key_down(Space);
+        input.key_up(Space);
+        assert_eq!(input.actions(&keymap)[&3].len(), 1);
+    }
+}
@@ -0,0 +1,359 @@
+use crate::simulation::{blob::OwnerId, InputEvent};
+use serde::Deserialize;
+use std::fmt;
+use std::path::Path;
+use winit::event::VirtualKeyCode;
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Deserialize)]
+pub enum Trigger {
+    // Active every tick while all keys are held.
+    Held,
+    // Active only in the tick the last key of the binding was pressed.
+    Pressed,
+}
+
+// One or more keys (a chord) that together give an input event for the
+// blobs of an owner.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Binding {
+    pub keys: Vec<VirtualKeyCode>,
+    pub owner: OwnerId,
+    pub event: InputEvent,
+    pub value: f32,
+    pub trigger: Trigger,
+}
+
+impl Binding {
+    pub fn new(key: VirtualKeyCode, owner: OwnerId, event: InputEvent) -> Self {
+        Binding {
+            keys: vec![key],
+            owner,
+            event,
+            value: 1.0,
+            trigger: Trigger::Held,
+        }
+    }
+
+    pub fn new_chord(
+        keys: &[VirtualKeyCode],
+        owner: OwnerId,
+        event: InputEvent,
+        value: f32,
+    ) -> Self {
+        Binding {
+            keys: keys.to_vec(),
+            owner,
+            event,
+            value,
+            trigger: Trigger::Held,
+        }
+    }
+}
+
+pub type KeyMap = Vec<Binding>;
+
+#[derive(Debug)]
+pub enum KeyMapError {
+    Io(std::io::Error),
+    // 'line' is 1-based, 0 if the position is unknown.
+    Invalid { line: usize, message: String },
+}
+
+impl fmt::Display for KeyMapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeyMapError::Io(error) => write!(f, "{}", error),
+            KeyMapError::Invalid { line: 0, message } => write!(f, "{}", message),
+            KeyMapError::Invalid { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+// The file is a list of bindings, e.g.
+//
+// [[binding]]
+// key = "Up"
+// owner = 0
+// event = "Forward"
+//
+// [[binding]]
+// keys = ["RControl", "Up"]
+// owner = 0
+// event = "Forward"
+// value = 2.0
+#[derive(Deserialize)]
+struct KeyMapFile {
+    #[serde(default)]
+    binding: Vec<BindingEntry>,
+}
+
+// Key and event names are checked after parsing, so errors can point to
+// the line they are on.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BindingEntry {
+    key: Option<toml::Spanned<String>>,
+    #[serde(default)]
+    keys: Vec<toml::Spanned<String>>,
+    // Called 'blob' in older files.
+    #[serde(alias = "blob")]
+    owner: OwnerId,
+    event: toml::Spanned<String>,
+    value: Option<f32>,
+    trigger: Option<toml::Spanned<String>>,
+}
+
+fn add_keys(
+    keymap: &mut KeyMap,
+    owner: OwnerId,
+    forward: VirtualKeyCode,
+    backward: VirtualKeyCode,
+    left: VirtualKeyCode,
+    right: VirtualKeyCode,
+) {
+    keymap.push(Binding::new(forward, owner, InputEvent::Forward));
+    keymap.push(Binding::new(backward, owner, InputEvent::Backward));
+    keymap.push(Binding::new(left, owner, InputEvent::TurnLeft));
+    keymap.push(Binding::new(right, owner, InputEvent::TurnRight));
+}
+
+pub fn default_keymap() -> KeyMap {
+    use VirtualKeyCode::*;
+    let mut keymap = KeyMap::new();
+
+    add_keys(&mut keymap, 0, Up, Down, Left, Right);
+    add_keys(&mut keymap, 1, W, S, A, D);
+
+    // Full throttle.
+    keymap.push(Binding::new_chord(
+        &[RControl, Up],
+        0,
+        InputEvent::Forward,
+        2.0,
+    ));
+    keymap.push(Binding::new_chord(
+        &[LShift, W],
+        1,
+        InputEvent::Forward,
+        2.0,
+    ));
+
+    // Split once per key press, holding the key would split every step.
+    for (key, owner) in &[(RShift, 0), (Space, 1)] {
+        let mut binding = Binding::new(*key, *owner, InputEvent::Split);
+        binding.trigger = Trigger::Pressed;
+        keymap.push(binding);
+    }
+
+    // Holding fire shoots as fast as the cooldown allows.
+    keymap.push(Binding::new(Return, 0, InputEvent::Fire));
+    keymap.push(Binding::new(LControl, 1, InputEvent::Fire));
+
+    keymap
+}
+
+fn line_of(source: &str, offset: usize) -> usize {
+    source[..offset.min(source.len())].matches('\n').count() + 1
+}
+
+fn from_name<'de, T: Deserialize<'de>>(
+    source: &str,
+    name: &'de toml::Spanned<String>,
+) -> Result<T, KeyMapError> {
+    use serde::de::IntoDeserializer;
+
+    T::deserialize(name.get_ref().as_str().into_deserializer()).map_err(
+        |error: serde::de::value::Error| KeyMapError::Invalid {
+            line: line_of(source, name.start()),
+            message: error.to_string(),
+        },
+    )
+}
+
+fn same_keys(first: &[VirtualKeyCode], second: &[VirtualKeyCode]) -> bool {
+    first.len() == second.len() && first.iter().all(|key| second.contains(key))
+}
+
+pub fn parse(source: &str) -> Result<KeyMap, KeyMapError> {
+    let file: KeyMapFile = toml::from_str(source).map_err(|error| KeyMapError::Invalid {
+        line: error.line_col().map_or(0, |(line, _)| line + 1),
+        message: error.to_string(),
+    })?;
+
+    let mut keymap = KeyMap::new();
+    for entry in &file.binding {
+        let line = line_of(source, entry.event.start());
+        let names: Vec<&toml::Spanned<String>> = entry.key.iter().chain(&entry.keys).collect();
+        if names.is_empty() || (entry.key.is_some() && !entry.keys.is_empty()) {
+            return Err(KeyMapError::Invalid {
+                line,
+                message: "a binding needs either 'key' or 'keys'".to_string(),
+            });
+        }
+
+        let mut keys = Vec::<VirtualKeyCode>::new();
+        for name in &names {
+            keys.push(from_name(source, name)?);
+        }
+        let event: InputEvent = from_name(source, &entry.event)?;
+        let trigger = match &entry.trigger {
+            Some(trigger) => from_name(source, trigger)?,
+            None => Trigger::Held,
+        };
+
+        let value = entry.value.unwrap_or(1.0);
+        if !value.is_finite() {
+            return Err(KeyMapError::Invalid {
+                line,
+                message: format!("value {} isn't a number", value),
+            });
+        }
+
+        if keymap.iter().any(|binding| same_keys(&binding.keys, &keys)) {
+            return Err(KeyMapError::Invalid {
+                line: line_of(source, names[0].start()),
+                message: format!("keys {:?} are bound more than once", keys),
+            });
+        }
+        keymap.push(Binding {
+            keys,
+            owner: entry.owner,
+            event,
+            value,
+            trigger,
+        });
+    }
+
+    Ok(keymap)
+}
+
+pub fn load(path: &Path) -> Result<KeyMap, KeyMapError> {
+    match std::fs::read_to_string(path) {
+        Ok(source) => parse(&source),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(default_keymap()),
+        Err(error) => Err(KeyMapError::Io(error)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invalid_line(result: Result<KeyMap, KeyMapError>) -> usize {
+        match result {
+            Err(KeyMapError::Invalid { line, .. }) => line,
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let keymap = parse(
+            r#"
+            [[binding]]
+            key = "Up"
+            owner = 0
+            event = "Forward"
+
+            # 'blob' is still accepted for the owner.
+            [[binding]]
+            keys = ["LShift", "A"]
+            blob = 1
+            event = "TurnLeft"
+            value = 0.5
+            trigger = "Pressed"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            keymap,
+            vec![
+                Binding::new(VirtualKeyCode::Up, 0, InputEvent::Forward),
+                Binding {
+                    keys: vec![VirtualKeyCode::LShift, VirtualKeyCode::A],
+                    owner: 1,
+                    event: InputEvent::TurnLeft,
+                    value: 0.5,
+                    trigger: Trigger::Pressed,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let unknown_key = r#"
+            [[binding]]
+            key = "Up"
+            owner = 0
+            event = "Forward"
+
+            [[binding]]
+            key = "Upp"
+            owner = 0
+            event = "Backward"
+            "#;
+        assert_eq!(invalid_line(parse(unknown_key)), 8);
+
+        let unknown_event = r#"
+            [[binding]]
+            key = "Up"
+            owner = 0
+            event = "Jump"
+            "#;
+        assert_eq!(invalid_line(parse(unknown_event)), 5);
+
+        let duplicate = r#"
+            [[binding]]
+            keys = ["Up", "LShift"]
+            owner = 0
+            event = "Forward"
+
+            [[binding]]
+            keys = ["LShift", "Up"]
+            owner = 1
+            event = "Forward"
+            "#;
+        assert_eq!(invalid_line(parse(duplicate)), 8);
+
+        let no_keys = r#"
+            [[binding]]
+            owner = 0
+            event = "Forward"
+            "#;
+        assert_eq!(invalid_line(parse(no_keys)), 4);
+
+        let unknown_trigger = r#"
+            [[binding]]
+            key = "Up"
+            owner = 0
+            event = "Forward"
+            trigger = "Twice"
+            "#;
+        assert_eq!(invalid_line(parse(unknown_trigger)), 6);
+
+        let not_a_number = r#"
+            [[binding]]
+            key = "Up"
+            owner = 0
+            event = "Forward"
+            value = nan
+            "#;
+        assert_eq!(invalid_line(parse(not_a_number)), 5);
+
+        assert_eq!(invalid_line(parse("[[binding]]\nkey = ")), 2);
+    }
+
+    #[test]
+    fn test_keys_file_matches_defaults() {
+        let keymap = parse(include_str!("../keys.toml")).unwrap();
+        assert_eq!(keymap, default_keymap());
+    }
+
+    #[test]
+    fn test_load_missing_file() {
+        let keymap = load(Path::new("does/not/exist.toml")).unwrap();
+        assert_eq!(keymap, default_keymap());
+    }
+}
@@ -0,0 +1,331 @@
+use crate::math::circle::Circle;
+use crate::math::collision;
+use crate::math::point::PointF32;
+use crate::math::rect::Rect;
+use crate::ron_file::{from_ron, invalid, RonError};
+use crate::simulation::blob::BlobDesc;
+use crate::simulation::Simulation;
+use serde::Deserialize;
+use std::path::Path;
+
+// A level in RON, e.g.
+//
+// (
+//     arena: (bottom_left: (x: -500.0, y: -500.0), top_right: (x: 500.0, y: 500.0)),
+//     obstacles: [(bottom_left: (x: -50.0, y: -50.0), top_right: (x: 50.0, y: 50.0))],
+//     spawns: [(pos: (x: -200.0, y: 0.0)), (pos: (x: 200.0, y: 0.0), rotation: 3.14)],
+//     food_density: 5.0,
+//     food_seed: 42,
+//     physics: (wall_restitution: 0.8),
+// )
+//
+// Everything but the arena and the spawns is optional. The blobs are
+// spawned in order, so the first spawn is the blob of owner 0 and so on.
+
+const DEFAULT_RADIUS: f32 = 10.0;
+// 'food_density' is the number of pellets in an area of this size.
+const FOOD_AREA: f32 = 100.0 * 100.0;
+
+pub type LevelError = RonError;
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Spawn {
+    pub pos: PointF32,
+    #[serde(default = "default_radius")]
+    pub radius: f32,
+    #[serde(default)]
+    pub rotation: f32,
+}
+
+fn default_radius() -> f32 {
+    DEFAULT_RADIUS
+}
+
+// Settings that are left out keep the values the simulation has.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Physics {
+    pub wall_restitution: Option<f32>,
+    pub damage_threshold: Option<f32>,
+    pub damage_scale: Option<f32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Level {
+    pub arena: Rect,
+    #[serde(default)]
+    pub obstacles: Vec<Rect>,
+    pub spawns: Vec<Spawn>,
+    #[serde(default)]
+    pub food_density: f32,
+    #[serde(default)]
+    pub food_seed: u64,
+    #[serde(default)]
+    pub physics: Physics,
+}
+
+fn is_valid(rect: &Rect) -> bool {
+    rect.bottom_left.x < rect.top_right.x && rect.bottom_left.y < rect.top_right.y
+}
+
+fn contains(outer: &Rect, inner: &Rect) -> bool {
+    outer.bottom_left.x <= inner.bottom_left.x
+        && outer.bottom_left.y <= inner.bottom_left.y
+        && inner.top_right.x <= outer.top_right.x
+        && inner.top_right.y <= outer.top_right.y
+}
+
+impl Level {
+    pub fn parse(source: &str) -> Result<Level, LevelError> {
+        let level: Level = from_ron(source)?;
+        level.check()?;
+        Ok(level)
+    }
+
+    pub fn load(path: &Path) -> Result<Level, LevelError> {
+        let source = std::fs::read_to_string(path).map_err(LevelError::Io)?;
+        Level::parse(&source)
+    }
+
+    // Things the format allows but that make no sense in a game.
+    fn check(&self) -> Result<(), LevelError> {
+        if !is_valid(&self.arena) {
+            return Err(invalid("the arena has no area".to_string()));
+        }
+        for (index, obstacle) in self.obstacles.iter().enumerate() {
+            if !is_valid(obstacle) {
+                return Err(invalid(format!("obstacle {} has no area", index)));
+            }
+            if !contains(&self.arena, obstacle) {
+                return Err(invalid(format!("obstacle {} is outside the arena", index)));
+            }
+        }
+
+        if self.spawns.is_empty() {
+            return Err(invalid("a level needs at least one spawn".to_string()));
+        }
+        for (index, spawn) in self.spawns.iter().enumerate() {
+            if !spawn.radius.is_finite() || spawn.radius <= 0.0 {
+                return Err(invalid(format!("spawn {} needs a positive radius", index)));
+            }
+            let reach = PointF32::new(spawn.radius, spawn.radius);
+            if !contains(
+                &self.arena,
+                &Rect::new(spawn.pos - reach, spawn.pos + reach),
+            ) {
+                return Err(invalid(format!("spawn {} is outside the arena", index)));
+            }
+            let circle = Circle::new_from_pos(spawn.pos, spawn.radius);
+            if let Some(obstacle) = self
+                .obstacles
+                .iter()
+                .position(|obstacle| collision::collides_rect(&circle, obstacle).is_some())
+            {
+                return Err(invalid(format!(
+                    "spawn {} overlaps obstacle {}",
+                    index, obstacle
+                )));
+            }
+        }
+
+        if !self.food_density.is_finite() || self.food_density < 0.0 {
+            return Err(invalid(
+                "food_density can't be negative or infinite".to_string(),
+            ));
+        }
+        if let Some(restitution) = self.physics.wall_restitution {
+            if !(0.0..=1.0).contains(&restitution) {
+                return Err(invalid(
+                    "wall_restitution has to be between 0 and 1".to_string(),
+                ));
+            }
+        }
+        for (name, value) in [
+            ("damage_threshold", self.physics.damage_threshold),
+            ("damage_scale", self.physics.damage_scale),
+        ] {
+            if value.is_some_and(|value| value.is_nan() || value < 0.0) {
+                return Err(invalid(format!("{} can't be negative", name)));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn food_count(&self) -> usize {
+        let size = self.arena.size();
+        (self.food_density * size.x * size.y / FOOD_AREA).round() as usize
+    }
+
+    pub fn simulation(&self) -> Simulation {
+        let mut simulation = Simulation::new_empty(self.arena);
+        self.apply(&mut simulation);
+        simulation.set_food(self.food_count(), self.food_seed);
+        for spawn in &self.spawns {
+            let mut desc = BlobDesc::new(spawn.pos.x, spawn.pos.y, spawn.radius);
+            desc.rotation = spawn.rotation;
+            simulation.spawn_blob(&desc);
+        }
+        simulation
+    }
+
+    // Applies the parts of the level that can change while the game runs,
+    // the physics and the obstacles. The arena, food and spawns need a new
+    // game.
+    pub fn apply(&self, simulation: &mut Simulation) {
+        if let Some(restitution) = self.physics.wall_restitution {
+            simulation.set_wall_restitution(restitution);
+        }
+        let (threshold, scale) = simulation.damage();
+        simulation.set_damage(
+            self.physics.damage_threshold.unwrap_or(threshold),
+            self.physics.damage_scale.unwrap_or(scale),
+        );
+        simulation.clear_obstacles();
+        for obstacle in &self.obstacles {
+            simulation.add_obstacle(*obstacle);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invalid_line<T>(result: Result<T, LevelError>) -> usize {
+        match result {
+            Err(LevelError::Invalid { line, .. }) => line,
+            _ => panic!(),
+        }
+    }
+
+    fn invalid_message<T>(result: Result<T, LevelError>) -> String {
+        match result {
+            Err(LevelError::Invalid { message, .. }) => message,
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let level = Level::parse(
+            r#"(
+                arena: (bottom_left: (x: -500.0, y: -500.0), top_right: (x: 500.0, y: 500.0)),
+                obstacles: [(bottom_left: (x: -50.0, y: -50.0), top_right: (x: 50.0, y: 50.0))],
+                spawns: [(pos: (x: -200.0, y: 0.0)), (pos: (x: 200.0, y: 0.0), radius: 20.0)],
+                food_density: 2.5,
+                food_seed: 42,
+                physics: (wall_restitution: 0.8),
+            )"#,
+        )
+        .unwrap();
+
+        assert_eq!(level.obstacles.len(), 1);
+        assert_eq!(level.spawns[0].radius, DEFAULT_RADIUS);
+        assert_eq!(level.food_count(), 250);
+
+        let simulation = level.simulation();
+        assert_eq!(simulation.rect(), level.arena);
+        assert_eq!(simulation.obstacles(), &level.obstacles[..]);
+        assert_eq!(simulation.food_settings(), (250, 42));
+        assert_eq!(simulation.wall_restitution(), 0.8);
+        assert_eq!(simulation.damage(), Simulation::new().damage());
+        assert_eq!(simulation.blobs().len(), 2);
+        assert_eq!(simulation.find_blob(1).unwrap().circle.radius, 20.0);
+    }
+
+    #[test]
+    fn test_default_level() {
+        // 'Simulation::new' plays this level.
+        let simulation = Simulation::new();
+        assert_eq!(
+            simulation.rect(),
+            Rect::new(
+                PointF32::new(-1000.0, -1000.0),
+                PointF32::new(1000.0, 1000.0)
+            )
+        );
+        assert_eq!(simulation.blobs().len(), 2);
+        assert_eq!(simulation.obstacles().len(), 2);
+        assert_eq!(simulation.food_settings(), (2000, 0x2545_f491_4f6c_dd1d));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let arena = "arena: (bottom_left: (x: 0.0, y: 0.0), top_right: (x: 100.0, y: 100.0))";
+
+        assert_eq!(
+            invalid_line(Level::parse(&format!(
+                "(\n{},\nspawns: [(pos: (x: 50.0, y: 50.0))],\nfood: 3,\n)",
+                arena
+            ))),
+            4
+        );
+        assert_eq!(
+            invalid_line(Level::parse(&format!(
+                "(\n{},\nspawns: [(pos: (x: 50.0 y: 50.0))],\n)",
+                arena
+            ))),
+            3
+        );
+
+        let message = |rest: &str| invalid_message(Level::parse(&format!("({}, {})", arena, rest)));
+        assert_eq!(message("spawns: []"), "a level needs at least one spawn");
+        assert_eq!(
+            message("spawns: [(pos: (x: 5.0, y: 50.0))]"),
+            "spawn 0 is outside the arena"
+        );
+        assert_eq!(
+            message(
+                "spawns: [(pos: (x: 50.0, y: 50.0))], \
+                 obstacles: [(bottom_left: (x: 55.0, y: 0.0), top_right: (x: 60.0, y: 200.0))]"
+            ),
+            "obstacle 0 is outside the arena"
+        );
+        assert_eq!(
+            message(
+                "spawns: [(pos: (x: 50.0, y: 50.0))], \
+                 obstacles: [(bottom_left: (x: 55.0, y: 0.0), top_right: (x: 60.0, y: 100.0))]"
+            ),
+            "spawn 0 overlaps obstacle 0"
+        );
+        assert_eq!(
+            message("spawns: [(pos: (x: 50.0, y: 50.0))], food_density: -1.0"),
+            "food_density can't be negative or infinite"
+        );
+        assert_eq!(
+            message("spawns: [(pos: (x: 50.0, y: 50.0))], physics: (wall_restitution: 2.0)"),
+            "wall_restitution has to be between 0 and 1"
+        );
+    }
+
+    #[test]
+    fn test_apply_to_running_game() {
+        let mut simulation = Simulation::new();
+        let level = Level::parse(
+            r#"(
+                arena: (bottom_left: (x: -10.0, y: -10.0), top_right: (x: 10.0, y: 10.0)),
+                spawns: [(pos: (x: 0.0, y: 0.0))],
+                physics: (wall_restitution: 0.1, damage_scale: 1.0),
+            )"#,
+        )
+        .unwrap();
+        level.apply(&mut simulation);
+
+        assert!(simulation.obstacles().is_empty());
+        assert_eq!(simulation.wall_restitution(), 0.1);
+        assert_eq!(simulation.damage().1, 1.0);
+        // The rest of the game stays as it is.
+        assert_eq!(simulation.rect(), Simulation::new().rect());
+        assert_eq!(simulation.blobs().len(), 2);
+    }
+
+    #[test]
+    fn test_load_missing_file() {
+        assert!(matches!(
+            Level::load(Path::new("does/not/exist.ron")),
+            Err(LevelError::Io(_))
+        ));
+    }
+}
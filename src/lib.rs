@@ -0,0 +1,20 @@
+pub mod level;
+pub mod logic;
+pub mod math;
+pub mod net;
+pub mod replay;
+pub mod ron_file;
+pub mod script;
+pub mod simulation;
+pub mod watch;
+
+#[cfg(feature = "gui")]
+pub mod camera;
+#[cfg(feature = "gui")]
+pub mod game;
+#[cfg(feature = "gui")]
+pub mod input;
+#[cfg(feature = "gui")]
+pub mod keymap;
+#[cfg(feature = "gui")]
+pub mod stopwatch;
@@ -79,8 +79,14 @@ impl<T: GridObject> LooseGrid<T> {
 
     pub fn add(&mut self, item: T) {
         let grid_pos = self.grid_pos.grid_for(&item.pos());
-
-        self.grid.value(grid_pos.x, grid_pos.y).objects.push(item);
+        let radius = PointF32::new(item.radius(), item.radius());
+        let bounds = Rect::new(item.pos() - radius, item.pos() + radius);
+
+        // The bounds are included right away so the item can be found before
+        // the next 'update'.
+        let quad = self.grid.value(grid_pos.x, grid_pos.y);
+        quad.object_bounds.include(&bounds);
+        quad.objects.push(item);
     }
 
     pub fn update(&mut self) {
@@ -103,6 +109,13 @@ impl<T: GridObject> LooseGrid<T> {
         });
     }
 
+    pub fn for_each_mut(&mut self, func: impl FnMut(&mut T)) {
+        self.grid
+            .iter_mut()
+            .flat_map(|quad| quad.objects.iter_mut())
+            .for_each(func);
+    }
+
     pub fn remove_if(&mut self, predicate: impl Fn(&T) -> bool) {
         self.grid.iter_mut().for_each(|quad| {
             quad.objects.retain(|item| !predicate(&item));
@@ -115,7 +128,7 @@ impl<T: GridObject> LooseGrid<T> {
             .filter(|quad| rect.collides(&quad.object_bounds))
             .flat_map(|quad| quad.objects.iter())
             .map(|object| (object, Circle::new_from_pos(object.pos(), object.radius())))
-            .filter(|(_, circle)| collision::collides_rect(&circle, &rect))
+            .filter(|(_, circle)| collision::collides_rect(&circle, &rect).is_some())
             .for_each(|(object, _)| func(object));
     }
 
@@ -128,7 +141,7 @@ impl<T: GridObject> LooseGrid<T> {
                 let circle = Circle::new_from_pos(object.pos(), object.radius());
                 (object, circle)
             })
-            .filter(|(_, circle)| collision::collides_rect(&circle, &rect))
+            .filter(|(_, circle)| collision::collides_rect(&circle, &rect).is_some())
             .for_each(|(object, _)| func(object));
     }
 
@@ -139,7 +152,7 @@ impl<T: GridObject> LooseGrid<T> {
             if rect.collides(&quad.object_bounds) {
                 for item in &quad.objects {
                     let circle = Circle::new_from_pos(item.pos(), item.radius());
-                    if collision::collides_rect(&circle, &rect) {
+                    if collision::collides_rect(&circle, &rect).is_some() {
                         func(item);
                     }
                 }
@@ -154,7 +167,7 @@ impl<T: GridObject> LooseGrid<T> {
             if rect.collides(&quad.object_bounds) {
                 for item in &mut quad.objects {
                     let circle = Circle::new_from_pos(item.pos(), item.radius());
-                    if collision::collides_rect(&circle, &rect) {
+                    if collision::collides_rect(&circle, &rect).is_some() {
                         func(item);
                     }
                 }
@@ -226,6 +239,55 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_add_before_update() {
+        let mut grid = LooseGrid::<Object>::new(
+            Rect::new(PointF32::new(0.0, 0.0), PointF32::new(100.0, 100.0)),
+            4,
+            5,
+        );
+        grid.update();
+
+        let object = Object {
+            pos: PointF32::new(30.0, 30.0),
+            radius: 5.0,
+            alive: true,
+        };
+        grid.add(object);
+
+        let mut found = Vec::<Object>::new();
+        grid.for_objects(
+            &Rect::new(PointF32::new(20.0, 20.0), PointF32::new(40.0, 40.0)),
+            |item| found.push(*item),
+        );
+        assert_eq!(found, vec![object]);
+    }
+
+    #[test]
+    fn test_for_each_mut() {
+        let mut grid = LooseGrid::<Object>::new(
+            Rect::new(PointF32::new(0.0, 0.0), PointF32::new(100.0, 100.0)),
+            4,
+            5,
+        );
+        grid.add(Object {
+            pos: PointF32::new(10.0, 10.0),
+            radius: 5.0,
+            alive: true,
+        });
+
+        grid.for_each_mut(|object| object.pos = PointF32::new(90.0, 90.0));
+        grid.update();
+
+        let mut found = Vec::<Object>::new();
+        grid.for_objects(
+            &Rect::new(PointF32::new(80.0, 80.0), PointF32::new(100.0, 100.0)),
+            |item| found.push(*item),
+        );
+        assert_eq!(found.len(), 1);
+        assert_eq!(grid.grid.value(3, 4).objects.len(), 1);
+    }
+
     #[test]
     fn test_for_objects() {
         let mut grid = LooseGrid::<Object>::new(
@@ -4,18 +4,29 @@ use crate::math::rect::Rect;
 pub struct GridPos {
     bounds: Rect,
     quad_size: PointF32,
+    last: PointU32,
 }
 
 impl GridPos {
     pub fn new(bounds: Rect, quad_size: PointF32) -> Self {
-        GridPos { bounds, quad_size }
+        let size = bounds.size();
+        let last = PointU32 {
+            x: ((size.x / quad_size.x).round() as u32).max(1) - 1,
+            y: ((size.y / quad_size.y).round() as u32).max(1) - 1,
+        };
+        GridPos {
+            bounds,
+            quad_size,
+            last,
+        }
     }
 
     pub fn grid_for(&self, pos: &PointF32) -> PointU32 {
+        // Positions outside of the bounds end up in the closest edge quad.
         let rel_pos = pos - &self.bounds.bottom_left;
         PointU32 {
-            x: (rel_pos.x / self.quad_size.x) as u32,
-            y: (rel_pos.y / self.quad_size.y) as u32,
+            x: ((rel_pos.x / self.quad_size.x) as u32).min(self.last.x),
+            y: ((rel_pos.y / self.quad_size.y) as u32).min(self.last.y),
         }
     }
 }
@@ -144,6 +155,31 @@ impl<'a, T> Iterator for Vec2dIterMut<'a, T> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_grid_pos_outside_bounds() {
+        let grid_pos = GridPos::new(
+            Rect::new(PointF32::new(-10.0, 0.0), PointF32::new(10.0, 40.0)),
+            PointF32::new(5.0, 10.0),
+        );
+
+        assert_eq!(
+            grid_pos.grid_for(&PointF32::new(-7.0, 15.0)),
+            PointU32::new(0, 1)
+        );
+        assert_eq!(
+            grid_pos.grid_for(&PointF32::new(-20.0, -5.0)),
+            PointU32::new(0, 0)
+        );
+        assert_eq!(
+            grid_pos.grid_for(&PointF32::new(10.0, 40.0)),
+            PointU32::new(3, 3)
+        );
+        assert_eq!(
+            grid_pos.grid_for(&PointF32::new(100.0, 15.0)),
+            PointU32::new(3, 1)
+        );
+    }
+
     #[test]
     fn test_vec2d_default() {
         let mut vec = Vec2d::<i32>::new(2, 7, 33);
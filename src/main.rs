@@ -1,21 +1,168 @@
-mod game;
-mod logic;
-mod math;
-mod simulation;
-mod stopwatch;
-
+use blobber::level::Level;
+use blobber::net::client::Client;
+use blobber::net::rollback::RollbackSession;
+use blobber::net::transport::UdpTransport;
+use blobber::replay::{Replay, ReplaySetup, ReplayWriter};
+use blobber::simulation::blob::OwnerId;
+use blobber::simulation::{Simulation, Tuning};
+use blobber::watch::FileWatcher;
+use blobber::{game, keymap, math};
 use femtovg::Color;
 use femtovg::{renderer::OpenGl, Canvas};
 use glutin::ContextBuilder;
-use std::collections::HashSet;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 use winit::{
-    event::{Event, KeyboardInput, WindowEvent},
+    event::{Event, KeyboardInput, MouseScrollDelta, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
     window::WindowBuilder,
 };
 
+const USAGE: &str = "usage: blobber [--level <file>] [--record <file>]\n       \
+                     blobber --replay <file>\n       \
+                     blobber --peer <address> [--bind <address>] \
+                     [--player <0|1>]\n       \
+                     blobber --connect <server address>";
+
+// How often the settings files are checked for changes.
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+struct Options {
+    // Every game is recorded, by default to 'last.replay'.
+    record: PathBuf,
+    replay: Option<PathBuf>,
+    // Played instead of the default arena, a replay or a server bring their
+    // own and peers play the default one, they have to simulate the same
+    // game.
+    level: Option<PathBuf>,
+    // Online match against 'peer', the local player controls the blobs of
+    // owner 'player' and the peer the other ones.
+    peer: Option<String>,
+    bind: String,
+    player: OwnerId,
+    // Join a 'blobber-server'.
+    connect: Option<String>,
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut options = Options {
+        record: PathBuf::from("last.replay"),
+        replay: None,
+        level: None,
+        peer: None,
+        bind: "0.0.0.0:7777".to_string(),
+        player: 0,
+        connect: None,
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--record" => options.record = args.next().ok_or("--record needs a file")?.into(),
+            "--replay" => options.replay = Some(args.next().ok_or("--replay needs a file")?.into()),
+            "--level" => options.level = Some(args.next().ok_or("--level needs a file")?.into()),
+            "--peer" => options.peer = Some(args.next().ok_or("--peer needs an address")?),
+            "--bind" => options.bind = args.next().ok_or("--bind needs an address")?,
+            "--player" => {
+                options.player = match args.next().as_deref() {
+                    Some("0") => 0,
+                    Some("1") => 1,
+                    _ => return Err("--player needs 0 or 1".to_string()),
+                }
+            }
+            "--connect" => options.connect = Some(args.next().ok_or("--connect needs an address")?),
+            "--help" | "-h" => return Err(USAGE.to_string()),
+            _ => return Err(format!("unknown option '{}'\n{}", arg, USAGE)),
+        }
+    }
+
+    let modes = [
+        options.replay.is_some(),
+        options.peer.is_some(),
+        options.connect.is_some(),
+    ];
+    if modes.iter().filter(|mode| **mode).count() > 1 {
+        return Err("only one of --replay, --peer and --connect can be used".to_string());
+    }
+    if options.level.is_some() && modes.iter().any(|mode| *mode) {
+        return Err("--level can't be used with --replay, --peer or --connect".to_string());
+    }
+
+    Ok(options)
+}
+
+// Reloads the tuning and level files that changed, returns true if any of
+// them was applied. A file with errors is reported and the game goes on
+// with the old settings.
+fn reload_settings(
+    simulation: &mut Simulation,
+    tuning_watcher: &mut FileWatcher,
+    level_watcher: Option<&mut FileWatcher>,
+) -> bool {
+    let mut reloaded = false;
+    if tuning_watcher.changed() {
+        let path = tuning_watcher.path();
+        match Tuning::load(path) {
+            Ok(tuning) => {
+                simulation.set_tuning(tuning);
+                println!("{}: reloaded", path.display());
+                reloaded = true;
+            }
+            Err(error) => eprintln!("{}: {}", path.display(), error),
+        }
+    }
+    if let Some(watcher) = level_watcher {
+        if watcher.changed() {
+            let path = watcher.path();
+            match Level::load(path) {
+                Ok(level) => {
+                    level.apply(simulation);
+                    println!(
+                        "{}: reloaded, the arena and spawns change in a new game",
+                        path.display()
+                    );
+                    reloaded = true;
+                }
+                Err(error) => eprintln!("{}: {}", path.display(), error),
+            }
+        }
+    }
+    reloaded
+}
+
 fn main() {
+    let options = parse_options().unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        std::process::exit(2);
+    });
+
+    let keymap_path = std::path::Path::new("keys.toml");
+    let keymap = match keymap::load(keymap_path) {
+        Ok(keymap) => keymap,
+        Err(error) => {
+            eprintln!("{}: {}", keymap_path.display(), error);
+            std::process::exit(1);
+        }
+    };
+
+    let tuning_path = Path::new("tuning.ron");
+    // Like the arena the tuning of peers has to be the same.
+    let tuning = if options.peer.is_some() {
+        Tuning::default()
+    } else {
+        Tuning::load(tuning_path).unwrap_or_else(|error| {
+            eprintln!("{}: {}", tuning_path.display(), error);
+            std::process::exit(1);
+        })
+    };
+    let level = options.level.as_ref().map(|path| {
+        Level::load(path).unwrap_or_else(|error| {
+            eprintln!("{}: {}", path.display(), error);
+            std::process::exit(1);
+        })
+    });
+
     let event_loop = EventLoop::new();
 
     let (renderer, windowed_context) = {
@@ -41,12 +188,84 @@ fn main() {
     let mut current_time = Instant::now();
     let mut render_time = Instant::now();
 
-    let mut game = game::Game::new();
+    let mut game = game::Game::new(keymap);
+    if let Some(level) = &level {
+        game.simulation = level.simulation();
+    }
+    game.simulation.set_tuning(tuning);
+
+    // A replay is played back instead of the keyboard, until it runs out.
+    // Otherwise the game is recorded, in deterministic mode so the
+    // recording plays back the same on every machine.
+    let replay = options.replay.as_ref().map(|path| {
+        Replay::load(path).unwrap_or_else(|error| {
+            eprintln!("{}: {}", path.display(), error);
+            std::process::exit(1);
+        })
+    });
+    let mut recorder = None;
+    let mut session = None;
+    let mut client = None;
+    let simulation_dt = match (&replay, &options.peer, &options.connect) {
+        (Some(replay), _, _) => {
+            game.simulation = replay.setup.simulation();
+            replay.setup.dt
+        }
+        // Online matches are not recorded, the inputs of a frame might
+        // only be known frames later.
+        (None, Some(peer), _) => {
+            let transport = UdpTransport::new(&options.bind, peer).unwrap_or_else(|error| {
+                eprintln!("{}: {}", options.bind, error);
+                std::process::exit(1);
+            });
+            session = Some(RollbackSession::new(
+                &mut game.simulation,
+                transport,
+                options.player,
+                1 - options.player,
+                dt.as_secs_f32(),
+            ));
+            dt.as_secs_f32()
+        }
+        // The server simulates, the client only shows what it gets.
+        (None, None, Some(server)) => {
+            let connected = Client::connect(server).unwrap_or_else(|error| {
+                eprintln!("{}: {}", server, error);
+                std::process::exit(1);
+            });
+            game.simulation = Simulation::new_empty(connected.rect());
+            for obstacle in connected.obstacles() {
+                game.simulation.add_obstacle(*obstacle);
+            }
+            game.camera.follow(&[connected.owner()]);
+            let server_dt = connected.dt();
+            client = Some(connected);
+            server_dt
+        }
+        (None, None, None) => {
+            game.simulation.set_deterministic(true);
+            let setup = ReplaySetup::from_simulation(&game.simulation, dt.as_secs_f32());
+            let writer = std::fs::File::create(&options.record)
+                .and_then(|file| ReplayWriter::new(BufWriter::new(file), &setup));
+            match writer {
+                Ok(writer) => recorder = Some(writer),
+                Err(error) => eprintln!("{}: {}", options.record.display(), error),
+            }
+            dt.as_secs_f32()
+        }
+    };
+    // Settings files are only reloaded when playing alone, online players or
+    // a replay would go out of sync.
+    let hot_reload = replay.is_none() && session.is_none() && client.is_none();
+    let mut tuning_watcher = FileWatcher::new(tuning_path);
+    let mut level_watcher = options.level.as_deref().map(FileWatcher::new);
+    let mut watch_time = Instant::now();
+    let mut step = 0u64;
+    // When the last state from the server arrived.
+    let mut state_time = Instant::now();
     let mut canvas = Canvas::new(renderer).expect("Cannot create canvas");
 
-    // More complicated way to keep track of inputs could be implemented. That
-    // keeps track of current keys down, what keys has been released.
-    let mut keys_down = HashSet::<winit::event::VirtualKeyCode>::new();
+    let mut cursor_pos = math::point::PointF32::new(0.0, 0.0);
 
     event_loop.run(move |event, _, control_flow| {
         use winit::event::ElementState;
@@ -64,7 +283,10 @@ fn main() {
                         },
                     ..
                 } => {
-                    keys_down.insert(*virtual_code);
+                    let newly_pressed = game.input.key_down(*virtual_code);
+                    if newly_pressed && *virtual_code == winit::event::VirtualKeyCode::F2 {
+                        game.toggle_split_screen();
+                    }
                 }
                 WindowEvent::KeyboardInput {
                     input:
@@ -75,12 +297,29 @@ fn main() {
                         },
                     ..
                 } => {
-                    keys_down.remove(virtual_code);
+                    game.input.key_up(*virtual_code);
+                }
+                WindowEvent::CursorMoved { position, .. } => {
+                    cursor_pos = math::point::PointF32::new(position.x as f32, position.y as f32);
+                }
+                WindowEvent::MouseWheel { delta, .. } => {
+                    let lines = match delta {
+                        MouseScrollDelta::LineDelta(_, y) => *y,
+                        MouseScrollDelta::PixelDelta(position) => position.y as f32 / 20.0,
+                    };
+                    game.zoom(1.1f32.powf(lines), cursor_pos);
                 }
                 WindowEvent::Resized(physical_size) => {
                     windowed_context.resize(*physical_size);
                 }
-                WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+                WindowEvent::CloseRequested => {
+                    if let Some(mut writer) = recorder.take() {
+                        if let Err(error) = writer.finish() {
+                            eprintln!("{}: {}", options.record.display(), error);
+                        }
+                    }
+                    *control_flow = ControlFlow::Exit;
+                }
                 _ => (),
             },
             Event::LoopDestroyed => return,
@@ -89,19 +328,69 @@ fn main() {
                 let frame_time = new_time - current_time;
                 current_time = new_time;
                 accumulator += frame_time;
+                if hot_reload && new_time - watch_time >= WATCH_INTERVAL {
+                    watch_time = new_time;
+                    let reloaded = reload_settings(
+                        &mut game.simulation,
+                        &mut tuning_watcher,
+                        level_watcher.as_mut(),
+                    );
+                    // The replay only has the settings of the start, it
+                    // wouldn't play back the same game any more.
+                    if reloaded {
+                        if let Some(mut writer) = recorder.take() {
+                            match writer.finish() {
+                                Ok(()) => eprintln!(
+                                    "{}: settings changed, recording stopped",
+                                    options.record.display()
+                                ),
+                                Err(error) => eprintln!("{}: {}", options.record.display(), error),
+                            }
+                        }
+                    }
+                }
                 while accumulator >= dt {
-                    // TODO Should really go through some kind of "input layer" (that can be owned
-                    // by the game); for keybindings, etc..
-                    // But also because the inputs might be needed by some other layer, e.g.
-                    // a menu want to do something with them.
-                    // This can of course also just be gathered by 'game', even as early as when
-                    // the key is pressed.
-                    let simulation_inputs = game.handle_inputs(&keys_down);
-                    game.simulation
-                        .simulate(&simulation_inputs, dt.as_secs_f32());
+                    let mut simulation_inputs = game.handle_inputs();
+                    if let Some(replay) = &replay {
+                        if step < replay.step_count() {
+                            simulation_inputs = replay.inputs(step);
+                        } else if step == replay.step_count() {
+                            println!(
+                                "replay finished after {} steps, state hash {:016x}",
+                                step,
+                                game.simulation.state_hash()
+                            );
+                        }
+                    }
+                    if let Some(writer) = &mut recorder {
+                        if let Err(error) = writer.record(&simulation_inputs) {
+                            eprintln!("{}: {}", options.record.display(), error);
+                            recorder = None;
+                        }
+                    }
+                    if let Some(client) = &mut client {
+                        let local_actions = simulation_inputs.remove(&0).unwrap_or_default();
+                        client.send_inputs(&local_actions);
+                    } else if let Some(session) = &mut session {
+                        // Online both players use the keys of the first one.
+                        let local_actions = simulation_inputs.remove(&0).unwrap_or_default();
+                        session.advance(&mut game.simulation, &local_actions);
+                    } else {
+                        game.simulation.simulate(&simulation_inputs, simulation_dt);
+                    }
+                    step += 1;
                     t += dt;
                     accumulator -= dt;
                 }
+                if let Some(client) = &mut client {
+                    if client.poll(&mut game.simulation) {
+                        state_time = Instant::now();
+                    }
+                    if !client.is_connected() {
+                        eprintln!("disconnected from the server");
+                        *control_flow = ControlFlow::Exit;
+                    }
+                }
                 window.request_redraw();
             }
             Event::RedrawRequested(_) => {
@@ -109,6 +398,7 @@ fn main() {
                 if since_last_render < target_frame_time {
                     std::thread::sleep(target_frame_time - since_last_render);
                 }
+                let frame_time = (Instant::now() - render_time).as_secs_f32();
                 render_time = Instant::now();
                 let size = window.inner_size();
                 let dpi_factor = window.scale_factor();
@@ -120,10 +410,14 @@ fn main() {
                     size.height as u32,
                     Color::rgbf(255.0, 255.0, 255.0),
                 );
-                let timepoint = 0f32; // TODO
+                // A client draws between the last two states it got.
+                let timepoint = match &client {
+                    Some(client) => (state_time.elapsed().as_secs_f32() / client.dt()).min(1.0),
+                    None => accumulator.as_secs_f32() / dt.as_secs_f32(),
+                };
                 canvas.save_with(|canvas| {
                     canvas.reset();
-                    game.render(timepoint, canvas);
+                    game.render(timepoint, frame_time, canvas);
                 });
                 canvas.flush();
                 windowed_context.swap_buffers().unwrap();
@@ -1,6 +1,7 @@
 use super::point::PointF32;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Circle {
     pub pos: PointF32,
     pub radius: f32,
@@ -54,23 +54,103 @@ pub fn may_collide(
     return rect1.collides(&rect2);
 }
 
-pub fn collides_rect(circle: &Circle, rect: &Rect) -> bool {
-    if rect.bottom_left.x <= circle.pos.x && circle.pos.x <= rect.top_right.x {
-        return (circle.pos.y < rect.top_right.y
-            && circle.pos.y + circle.radius >= rect.bottom_left.y)
-            || (circle.pos.y > rect.bottom_left.y
-                && circle.pos.y - circle.radius <= rect.top_right.y);
-    } else if rect.bottom_left.y <= circle.pos.y && circle.pos.y <= rect.top_right.y {
-        return (circle.pos.x < rect.top_right.x
-            && circle.pos.x + circle.radius >= rect.bottom_left.x)
-            || (circle.pos.x > rect.bottom_left.x
-                && circle.pos.x - circle.radius <= rect.top_right.x);
-    } else {
-        return circle.contains_pos(&rect.bottom_left)
-            || circle.contains_pos(&rect.top_left())
-            || circle.contains_pos(&rect.top_right)
-            || circle.contains_pos(&rect.bottom_right());
+// Where a circle overlaps a rect, 'normal' points away from the rect and
+// 'penetration' is how far the circle has to move along it to only touch
+// the rect.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Contact {
+    pub normal: PointF32,
+    pub penetration: f32,
+}
+
+pub fn collides_rect(circle: &Circle, rect: &Rect) -> Option<Contact> {
+    let closest = PointF32::new(
+        circle.pos.x.clamp(rect.bottom_left.x, rect.top_right.x),
+        circle.pos.y.clamp(rect.bottom_left.y, rect.top_right.y),
+    );
+    let delta = circle.pos - closest;
+    let distance_sqr = delta.dot(&delta);
+    if distance_sqr > 0f32 {
+        if distance_sqr > circle.radius * circle.radius {
+            return None;
+        }
+        let distance = distance_sqr.sqrt();
+        return Some(Contact {
+            normal: delta / distance,
+            penetration: circle.radius - distance,
+        });
     }
+
+    // The center is inside the rect, the circle is pushed out through the
+    // closest side.
+    let sides = [
+        (
+            circle.pos.x - rect.bottom_left.x,
+            PointF32::new(-1f32, 0f32),
+        ),
+        (rect.top_right.x - circle.pos.x, PointF32::new(1f32, 0f32)),
+        (
+            circle.pos.y - rect.bottom_left.y,
+            PointF32::new(0f32, -1f32),
+        ),
+        (rect.top_right.y - circle.pos.y, PointF32::new(0f32, 1f32)),
+    ];
+    let (depth, normal) =
+        sides[1..].iter().fold(
+            sides[0],
+            |closest, side| if side.0 < closest.0 { *side } else { closest },
+        );
+    Some(Contact {
+        normal,
+        penetration: depth + circle.radius,
+    })
+}
+
+// Time at which a circle moving with 'velocity' first touches 'rect', None
+// if it doesn't before 'time'. It is a slab test of the center against the
+// rect grown by the radius, so near the corners of the rect the hit is
+// found a little early.
+pub fn collides_rect_before(
+    circle: &Circle,
+    velocity: &Velocity,
+    rect: &Rect,
+    time: f32,
+) -> Option<f32> {
+    let mut grown = *rect;
+    grown.grow(circle.radius, circle.radius);
+
+    let mut t_min = 0f32;
+    let mut t_max = time;
+    for (pos, speed, min, max) in [
+        (
+            circle.pos.x,
+            velocity.x,
+            grown.bottom_left.x,
+            grown.top_right.x,
+        ),
+        (
+            circle.pos.y,
+            velocity.y,
+            grown.bottom_left.y,
+            grown.top_right.y,
+        ),
+    ] {
+        if speed.abs() <= f32::EPSILON {
+            if pos < min || pos > max {
+                return None;
+            }
+            continue;
+        }
+        // When the center enters and leaves this axis' range.
+        let enter = (min - pos) / speed;
+        let leave = (max - pos) / speed;
+        t_min = t_min.max(enter.min(leave));
+        t_max = t_max.min(enter.max(leave));
+        if t_min > t_max {
+            return None;
+        }
+    }
+    Some(t_min)
 }
 
 pub fn collides_circle(
@@ -94,6 +174,16 @@ pub fn collides_before(
     circle2: (&Circle, &Velocity),
     time: f32,
 ) -> Option<f32> {
+    // Circles that already overlap touch right away if they move towards
+    // each other, and not at all if they move apart. 'collides_circle' would
+    // give the time they stop overlapping.
+    let diff = circle2.0.pos - circle1.0.pos;
+    let radius = circle1.0.radius + circle2.0.radius;
+    if diff.dot(&diff) < radius * radius {
+        let approaching = diff.dot(&(circle2.1 - circle1.1)) < 0f32;
+        return if approaching { Some(0f32) } else { None };
+    }
+
     // TODO might be faster to first check 'may_collide'
     let collides_at = collides_circle(circle1, circle2);
     match collides_at {
@@ -252,13 +342,100 @@ mod tests {
         let c6 = Circle::new(1.0, 5.0, 2f32.sqrt() - 0.0001);
         let c7 = Circle::new(4.0, -2.0, 2.5);
 
-        assert!(collides_rect(&c1, &rect));
-        assert!(collides_rect(&c2, &rect));
-        assert!(collides_rect(&c3, &rect));
-        assert!(collides_rect(&c4, &rect));
-        assert!(collides_rect(&c5, &rect));
+        assert!(collides_rect(&c1, &rect).is_some());
+        assert!(collides_rect(&c2, &rect).is_some());
+        assert!(collides_rect(&c3, &rect).is_some());
+        assert!(collides_rect(&c4, &rect).is_some());
+        assert!(collides_rect(&c5, &rect).is_some());
+
+        assert!(collides_rect(&c6, &rect).is_none());
+        assert!(collides_rect(&c7, &rect).is_none());
+    }
+
+    #[test]
+    fn test_collides_before_overlapping() {
+        let first = Circle::new(0f32, 0f32, 10f32);
+        let second = Circle::new(15f32, 0f32, 10f32);
+        let still = PointF32::new(0f32, 0f32);
+
+        assert_eq!(
+            collides_before(
+                (&first, &PointF32::new(10f32, 0f32)),
+                (&second, &still),
+                1f32
+            ),
+            Some(0f32)
+        );
+        assert_eq!(
+            collides_before(
+                (&first, &PointF32::new(-10f32, 0f32)),
+                (&second, &still),
+                1f32
+            ),
+            None
+        );
+        assert_eq!(
+            collides_before((&first, &still), (&second, &still), 1f32),
+            None
+        );
+    }
+
+    #[test]
+    fn test_collides_rect_before() {
+        let rect = Rect::new(PointF32::new(10.0, -5.0), PointF32::new(12.0, 5.0));
+        let circle = Circle::new(0.0, 0.0, 1.0);
+
+        assert_eq!(
+            collides_rect_before(&circle, &PointF32::new(10.0, 0.0), &rect, 1.0),
+            Some(0.9)
+        );
+        // Too slow to get there in time.
+        assert_eq!(
+            collides_rect_before(&circle, &PointF32::new(5.0, 0.0), &rect, 1.0),
+            None
+        );
+        // Passing beside it, or moving away from it.
+        assert_eq!(
+            collides_rect_before(&circle, &PointF32::new(20.0, 20.0), &rect, 1.0),
+            None
+        );
+        assert_eq!(
+            collides_rect_before(&circle, &PointF32::new(-10.0, 0.0), &rect, 1.0),
+            None
+        );
+        // Passing right through a thin rect within the time.
+        let thin = Rect::new(PointF32::new(5.0, -5.0), PointF32::new(5.1, 5.0));
+        assert_eq!(
+            collides_rect_before(&circle, &PointF32::new(100.0, 0.0), &thin, 1.0),
+            Some(0.04)
+        );
+        // Already touching.
+        let inside = Circle::new(11.0, 0.0, 1.0);
+        assert_eq!(
+            collides_rect_before(&inside, &PointF32::new(0.0, 0.0), &rect, 1.0),
+            Some(0.0)
+        );
+    }
+
+    #[test]
+    fn test_rect_contact() {
+        let rect = Rect::new(PointF32::new(2.0, 1.0), PointF32::new(7.0, 4.0));
 
-        assert!(!collides_rect(&c6, &rect));
-        assert!(!collides_rect(&c7, &rect));
+        // Overlapping the top side.
+        let contact = collides_rect(&Circle::new(5.0, 4.25, 0.75), &rect).unwrap();
+        assert_eq!(contact.normal, PointF32::new(0.0, 1.0));
+        assert!((contact.penetration - 0.5).abs() < 0.00001);
+
+        // Overlapping a corner, the normal points away from it.
+        let contact = collides_rect(&Circle::new(8.0, 0.0, 2.0), &rect).unwrap();
+        let diagonal = 0.5f32.sqrt();
+        assert!((contact.normal.x - diagonal).abs() < 0.00001);
+        assert!((contact.normal.y + diagonal).abs() < 0.00001);
+        assert!((contact.penetration - (2.0 - 2f32.sqrt())).abs() < 0.00001);
+
+        // Inside, pushed out through the closest side.
+        let contact = collides_rect(&Circle::new(2.5, 3.0, 1.0), &rect).unwrap();
+        assert_eq!(contact.normal, PointF32::new(-1.0, 0.0));
+        assert!((contact.penetration - 1.5).abs() < 0.00001);
     }
 }
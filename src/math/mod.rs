@@ -1,4 +1,6 @@
 pub mod circle;
 pub mod collision;
 pub mod point;
+pub mod random;
 pub mod rect;
+pub mod trig;
@@ -0,0 +1,7 @@
+// Xorshift, uniform in [0, 1). The state must not be 0.
+pub fn xorshift(state: &mut u64) -> f32 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    (*state >> 40) as f32 / (1u64 << 24) as f32
+}
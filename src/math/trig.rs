@@ -0,0 +1,57 @@
+// Sine and cosine using only basic floating point operations, which are
+// exactly specified by IEEE 754, unlike 'f32::sin' and 'f32::cos' that
+// depend on the platform's math library. Used when the simulation has to
+// give bit-exact results everywhere.
+
+use std::f32::consts::{FRAC_PI_2, PI};
+
+const TAU: f32 = 2.0 * PI;
+
+// Reduces 'x' to [-pi, pi].
+fn reduce(x: f32) -> f32 {
+    x - (x / TAU).round() * TAU
+}
+
+// Taylor series around 0, good enough on [-pi/2, pi/2].
+fn sin_poly(x: f32) -> f32 {
+    let x2 = x * x;
+    x * (1.0
+        + x2 * (-1.0 / 6.0
+            + x2 * (1.0 / 120.0
+                + x2 * (-1.0 / 5040.0 + x2 * (1.0 / 362_880.0 + x2 * (-1.0 / 39_916_800.0))))))
+}
+
+pub fn sin(x: f32) -> f32 {
+    let x = reduce(x);
+    // sin(x) = sin(pi - x), to get into [-pi/2, pi/2].
+    if x > FRAC_PI_2 {
+        sin_poly(PI - x)
+    } else if x < -FRAC_PI_2 {
+        sin_poly(-PI - x)
+    } else {
+        sin_poly(x)
+    }
+}
+
+pub fn cos(x: f32) -> f32 {
+    sin(x + FRAC_PI_2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sin_cos() {
+        let mut x = -20.0f32;
+        while x < 20.0 {
+            assert!((sin(x) - x.sin()).abs() < 1e-5, "sin({})", x);
+            assert!((cos(x) - x.cos()).abs() < 1e-5, "cos({})", x);
+            x += 0.01;
+        }
+
+        assert_eq!(sin(0.0), 0.0);
+        assert!((sin(FRAC_PI_2) - 1.0).abs() < 1e-6);
+        assert!((cos(PI) + 1.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,202 @@
+use super::protocol::{
+    self, InputPacket, ProjectileState, StateAssembler, StatePacket, Welcome, WorldState,
+};
+use crate::math::circle::Circle;
+use crate::math::rect::Rect;
+use crate::simulation::blob::{Blob, BlobDesc, BlobId, OwnerId};
+use crate::simulation::projectile::Projectile;
+use crate::simulation::{Action, Simulation};
+use std::collections::VecDeque;
+use std::io::{self, Read};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+// States kept to apply the server's deltas to.
+const STATE_HISTORY: usize = 64;
+
+// Connection to a 'Server'. The client doesn't simulate anything itself,
+// it copies the states it receives into a simulation that is only used for
+// rendering.
+pub struct Client {
+    stream: TcpStream,
+    socket: UdpSocket,
+    server_addr: SocketAddr,
+    welcome: Welcome,
+    sequence: u64,
+    states: VecDeque<(u64, WorldState)>,
+    // Projectiles of the latest state.
+    projectiles: Vec<ProjectileState>,
+    assembler: StateAssembler,
+    connected: bool,
+}
+
+impl Client {
+    pub fn connect(addr: impl ToSocketAddrs) -> io::Result<Client> {
+        let mut stream = TcpStream::connect(addr)?;
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        let welcome: Welcome = protocol::read_message(&mut stream)?;
+        stream.set_nonblocking(true)?;
+
+        let socket = UdpSocket::bind(SocketAddr::new(stream.local_addr()?.ip(), 0))?;
+        socket.set_nonblocking(true)?;
+        let server_addr = SocketAddr::new(stream.peer_addr()?.ip(), welcome.udp_port);
+
+        Ok(Client {
+            stream,
+            socket,
+            server_addr,
+            welcome,
+            sequence: 0,
+            states: VecDeque::new(),
+            projectiles: Vec::new(),
+            assembler: StateAssembler::default(),
+            connected: true,
+        })
+    }
+
+    // Owner of the blobs this client controls.
+    pub fn owner(&self) -> OwnerId {
+        self.welcome.owner
+    }
+
+    pub fn rect(&self) -> Rect {
+        self.welcome.rect
+    }
+
+    pub fn obstacles(&self) -> &[Rect] {
+        &self.welcome.obstacles
+    }
+
+    // The server's time step.
+    pub fn dt(&self) -> f32 {
+        self.welcome.dt
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    // Step of the latest state received.
+    pub fn step(&self) -> Option<u64> {
+        self.states.back().map(|(step, _)| *step)
+    }
+
+    pub fn send_inputs(&mut self, actions: &[Action]) {
+        self.sequence += 1;
+        let packet = InputPacket {
+            owner: self.welcome.owner,
+            token: self.welcome.token,
+            sequence: self.sequence,
+            ack: self.step(),
+            actions: actions.to_vec(),
+        };
+        if let Ok(bytes) = bincode::serialize(&packet) {
+            let _ = self.socket.send_to(&bytes, self.server_addr);
+        }
+    }
+
+    // Receives states from the server, returns true if 'simulation' was
+    // updated to a newer one.
+    pub fn poll(&mut self, simulation: &mut Simulation) -> bool {
+        let mut buffer = [0u8; 64];
+        match self.stream.read(&mut buffer) {
+            Ok(0) => self.connected = false,
+            Err(error) if error.kind() != io::ErrorKind::WouldBlock => self.connected = false,
+            _ => (),
+        }
+
+        let mut updated = false;
+        let mut buffer = vec![0u8; 65536];
+        while let Ok((size, from)) = self.socket.recv_from(&mut buffer) {
+            if from != self.server_addr {
+                continue;
+            }
+            let packet: StatePacket = match bincode::deserialize(&buffer[..size]) {
+                Ok(packet) => packet,
+                Err(_) => continue,
+            };
+            if self.step().is_some_and(|step| packet.step <= step) {
+                continue;
+            }
+            let packet = match self.assembler.add(packet) {
+                Some(packet) => packet,
+                None => continue,
+            };
+
+            let empty = WorldState::new();
+            let baseline = match packet.baseline {
+                None => &empty,
+                Some(baseline) => match self.states.iter().find(|(step, _)| *step == baseline) {
+                    Some((_, state)) => state,
+                    None => continue,
+                },
+            };
+            if let Some(state) = protocol::apply(baseline, &packet.changed, &packet.removed) {
+                self.states.push_back((packet.step, state));
+                self.projectiles = packet.projectiles;
+                if self.states.len() > STATE_HISTORY {
+                    self.states.pop_front();
+                }
+                updated = true;
+            }
+        }
+
+        if updated {
+            Client::mirror(&self.states.back().unwrap().1, simulation);
+            // Drawn from where they were a step ago, like the blobs.
+            let dt = self.welcome.dt;
+            let projectiles = self
+                .projectiles
+                .iter()
+                .map(|state| {
+                    let mut projectile =
+                        Projectile::new(state.shooter, state.owner, state.pos, state.velocity);
+                    projectile.previous_pos = state.pos - state.velocity * dt;
+                    projectile
+                })
+                .collect();
+            simulation.set_projectiles(projectiles);
+            // Nothing plays the spawns and removals of a mirror.
+            simulation.take_events();
+        }
+        updated
+    }
+
+    // The previous position of each blob is kept, so the renderer
+    // interpolates from the last state to the new one.
+    fn mirror(state: &WorldState, simulation: &mut Simulation) {
+        let removed: Vec<BlobId> = simulation
+            .blobs()
+            .iter()
+            .map(|blob| blob.id)
+            .filter(|id| !state.contains_key(id))
+            .collect();
+        for blob_id in removed {
+            simulation.despawn(blob_id);
+        }
+
+        for (blob_id, blob_state) in state {
+            let mut blob = match simulation.find_blob(*blob_id) {
+                Some(old) => {
+                    let mut blob = old.clone();
+                    blob.previous_pos = old.circle.pos;
+                    blob.previous_rotation = old.rotation;
+                    blob
+                }
+                None => {
+                    let mut desc =
+                        BlobDesc::new(blob_state.pos.x, blob_state.pos.y, blob_state.radius);
+                    desc.rotation = blob_state.rotation;
+                    desc.owner = Some(blob_state.owner);
+                    Blob::new_from_desc(&desc, *blob_id)
+                }
+            };
+            blob.circle = Circle::new_from_pos(blob_state.pos, blob_state.radius);
+            blob.rotation = blob_state.rotation;
+            blob.owner = blob_state.owner;
+            blob.health = blob_state.health;
+            blob.max_health = blob_state.max_health;
+            simulation.set_blob(blob);
+        }
+    }
+}
@@ -0,0 +1,5 @@
+pub mod client;
+pub mod protocol;
+pub mod rollback;
+pub mod server;
+pub mod transport;
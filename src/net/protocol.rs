@@ -0,0 +1,481 @@
+use crate::math::point::PointF32;
+use crate::math::rect::Rect;
+use crate::simulation::blob::{BlobId, OwnerId};
+use crate::simulation::{Action, Simulation};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+
+// States are split in datagrams of at most this size, so they aren't
+// fragmented or dropped on the way.
+pub const MAX_STATE_PACKET: usize = 1200;
+// Messages over TCP that claim to be longer are refused, instead of
+// allocating whatever the other side asks for.
+pub const MAX_MESSAGE: usize = 1 << 20;
+
+// Messages between the server and its clients. A client connects over TCP
+// and gets a 'Welcome', after that it sends its inputs and gets the state
+// of the arena over UDP. The TCP connection stays open, the client has
+// left when it closes.
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Welcome {
+    // The client controls the blobs of this owner.
+    pub owner: OwnerId,
+    // Sent with every input, so others can't control our blobs.
+    pub token: u64,
+    pub udp_port: u16,
+    pub rect: Rect,
+    pub obstacles: Vec<Rect>,
+    pub dt: f32,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct InputPacket {
+    pub owner: OwnerId,
+    pub token: u64,
+    // Older inputs arriving late are ignored.
+    pub sequence: u64,
+    // The latest state the client has, deltas are sent against it.
+    pub ack: Option<u64>,
+    pub actions: Vec<Action>,
+}
+
+// What a client needs to draw a blob.
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub struct BlobState {
+    pub pos: PointF32,
+    pub radius: f32,
+    pub rotation: f32,
+    pub owner: OwnerId,
+    pub health: f32,
+    pub max_health: f32,
+}
+
+pub type WorldState = BTreeMap<BlobId, BlobState>;
+
+// The fields of a blob that changed, a new blob has all of them.
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub struct BlobDelta {
+    pub id: BlobId,
+    pub pos: Option<PointF32>,
+    pub radius: Option<f32>,
+    pub rotation: Option<f32>,
+    pub owner: Option<OwnerId>,
+    pub health: Option<f32>,
+    pub max_health: Option<f32>,
+}
+
+// What a client needs to draw a projectile.
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub struct ProjectileState {
+    pub pos: PointF32,
+    pub velocity: PointF32,
+    pub shooter: BlobId,
+    pub owner: OwnerId,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct StatePacket {
+    pub step: u64,
+    // The state the deltas are against, None if they are the full state.
+    pub baseline: Option<u64>,
+    // The deltas of a step are split over 'parts' packets, this one is
+    // number 'part'.
+    pub part: u16,
+    pub parts: u16,
+    pub changed: Vec<BlobDelta>,
+    pub removed: Vec<BlobId>,
+    // All projectiles, they don't live long enough to send deltas.
+    pub projectiles: Vec<ProjectileState>,
+}
+
+// Splits the deltas of a step in packets of at most 'max_size' bytes, there
+// is always at least one packet.
+pub fn split_state(
+    step: u64,
+    baseline: Option<u64>,
+    changed: Vec<BlobDelta>,
+    removed: Vec<BlobId>,
+    projectiles: Vec<ProjectileState>,
+    max_size: usize,
+) -> Vec<StatePacket> {
+    let empty = StatePacket {
+        step,
+        baseline,
+        part: 0,
+        parts: 0,
+        changed: Vec::new(),
+        removed: Vec::new(),
+        projectiles: Vec::new(),
+    };
+    // Bincode writes the items of a vector one after another, so a packet is
+    // as big as the empty one plus its items.
+    let header = bincode::serialized_size(&empty).map_or(0, |size| size as usize);
+    let item_size = |item_size: bincode::Result<u64>| item_size.map_or(0, |size| size as usize);
+
+    let mut packets = vec![empty.clone()];
+    let mut size = header;
+    let mut make_room = |packets: &mut Vec<StatePacket>, item_size: usize| {
+        if size + item_size > max_size && size > header {
+            packets.push(empty.clone());
+            size = header;
+        }
+        size += item_size;
+    };
+    for delta in changed {
+        make_room(&mut packets, item_size(bincode::serialized_size(&delta)));
+        packets.last_mut().unwrap().changed.push(delta);
+    }
+    for id in removed {
+        make_room(&mut packets, item_size(bincode::serialized_size(&id)));
+        packets.last_mut().unwrap().removed.push(id);
+    }
+    for projectile in projectiles {
+        make_room(
+            &mut packets,
+            item_size(bincode::serialized_size(&projectile)),
+        );
+        packets.last_mut().unwrap().projectiles.push(projectile);
+    }
+
+    let parts = packets.len() as u16;
+    for (part, packet) in packets.iter_mut().enumerate() {
+        packet.part = part as u16;
+        packet.parts = parts;
+    }
+    packets
+}
+
+// Puts the parts of a state back together. Only the newest state is
+// collected, parts of older ones are dropped.
+#[derive(Default)]
+pub struct StateAssembler {
+    parts: Vec<Option<StatePacket>>,
+}
+
+impl StateAssembler {
+    // Returns the whole state once all its parts arrived.
+    pub fn add(&mut self, packet: StatePacket) -> Option<StatePacket> {
+        if packet.part >= packet.parts {
+            return None;
+        }
+        let pending = self.parts.iter().flatten().next();
+        if let Some(pending) = pending {
+            if packet.step < pending.step {
+                return None;
+            }
+            if packet.step > pending.step {
+                self.parts.clear();
+            } else if packet.parts as usize != self.parts.len() {
+                return None;
+            }
+        }
+        if self.parts.is_empty() {
+            self.parts.resize(packet.parts as usize, None);
+        }
+
+        let part = packet.part as usize;
+        self.parts[part] = Some(packet);
+        if self.parts.iter().any(|part| part.is_none()) {
+            return None;
+        }
+        let mut parts = self.parts.drain(..).flatten();
+        let mut state = parts.next().unwrap();
+        for part in parts {
+            state.changed.extend(part.changed);
+            state.removed.extend(part.removed);
+            state.projectiles.extend(part.projectiles);
+        }
+        state.part = 0;
+        state.parts = 1;
+        Some(state)
+    }
+}
+
+pub fn world_state(simulation: &Simulation) -> WorldState {
+    simulation
+        .blobs()
+        .iter()
+        .map(|blob| {
+            (
+                blob.id,
+                BlobState {
+                    pos: blob.circle.pos,
+                    radius: blob.circle.radius,
+                    rotation: blob.rotation,
+                    owner: blob.owner,
+                    health: blob.health,
+                    max_health: blob.max_health,
+                },
+            )
+        })
+        .collect()
+}
+
+pub fn projectile_states(simulation: &Simulation) -> Vec<ProjectileState> {
+    simulation
+        .projectiles()
+        .iter()
+        .map(|projectile| ProjectileState {
+            pos: projectile.circle.pos,
+            velocity: projectile.velocity,
+            shooter: projectile.shooter,
+            owner: projectile.owner,
+        })
+        .collect()
+}
+
+pub fn diff(baseline: &WorldState, current: &WorldState) -> (Vec<BlobDelta>, Vec<BlobId>) {
+    let changed_fields = |old: Option<&BlobState>, new: &BlobState| {
+        let mut delta = BlobDelta::default();
+        if old.map(|old| old.pos) != Some(new.pos) {
+            delta.pos = Some(new.pos);
+        }
+        if old.map(|old| old.radius) != Some(new.radius) {
+            delta.radius = Some(new.radius);
+        }
+        if old.map(|old| old.rotation) != Some(new.rotation) {
+            delta.rotation = Some(new.rotation);
+        }
+        if old.map(|old| old.owner) != Some(new.owner) {
+            delta.owner = Some(new.owner);
+        }
+        if old.map(|old| old.health) != Some(new.health) {
+            delta.health = Some(new.health);
+        }
+        if old.map(|old| old.max_health) != Some(new.max_health) {
+            delta.max_health = Some(new.max_health);
+        }
+        delta
+    };
+
+    let changed = current
+        .iter()
+        .filter_map(|(id, state)| {
+            let old = baseline.get(id);
+            if old == Some(state) {
+                return None;
+            }
+            Some(BlobDelta {
+                id: *id,
+                ..changed_fields(old, state)
+            })
+        })
+        .collect();
+    let removed = baseline
+        .keys()
+        .filter(|id| !current.contains_key(id))
+        .copied()
+        .collect();
+    (changed, removed)
+}
+
+// Returns None if a blob that isn't in 'baseline' doesn't have all fields.
+pub fn apply(
+    baseline: &WorldState,
+    changed: &[BlobDelta],
+    removed: &[BlobId],
+) -> Option<WorldState> {
+    let mut state = baseline.clone();
+    for id in removed {
+        state.remove(id);
+    }
+    for delta in changed {
+        let blob = match state.get(&delta.id) {
+            Some(old) => BlobState {
+                pos: delta.pos.unwrap_or(old.pos),
+                radius: delta.radius.unwrap_or(old.radius),
+                rotation: delta.rotation.unwrap_or(old.rotation),
+                owner: delta.owner.unwrap_or(old.owner),
+                health: delta.health.unwrap_or(old.health),
+                max_health: delta.max_health.unwrap_or(old.max_health),
+            },
+            None => BlobState {
+                pos: delta.pos?,
+                radius: delta.radius?,
+                rotation: delta.rotation?,
+                owner: delta.owner?,
+                health: delta.health?,
+                max_health: delta.max_health?,
+            },
+        };
+        state.insert(delta.id, blob);
+    }
+    Some(state)
+}
+
+// Messages over TCP are prefixed with their length.
+pub fn write_message<T: Serialize>(writer: &mut impl Write, message: &T) -> io::Result<()> {
+    let bytes = bincode::serialize(message)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+    writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    writer.write_all(&bytes)
+}
+
+pub fn read_message<T: DeserializeOwned>(reader: &mut impl Read) -> io::Result<T> {
+    let mut length = [0u8; 4];
+    reader.read_exact(&mut length)?;
+    let length = u32::from_le_bytes(length) as usize;
+    if length > MAX_MESSAGE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("message of {} bytes is too long", length),
+        ));
+    }
+    let mut bytes = vec![0u8; length];
+    reader.read_exact(&mut bytes)?;
+    bincode::deserialize(&bytes).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blob(x: f32, rotation: f32) -> BlobState {
+        BlobState {
+            pos: PointF32::new(x, 0.0),
+            radius: 10.0,
+            rotation,
+            owner: 0,
+            health: 100.0,
+            max_health: 100.0,
+        }
+    }
+
+    #[test]
+    fn test_diff_apply() {
+        let baseline: WorldState = vec![
+            (0, blob(0.0, 0.0)),
+            (1, blob(50.0, 0.0)),
+            (2, blob(90.0, 1.0)),
+        ]
+        .into_iter()
+        .collect();
+        let current: WorldState = vec![
+            (0, blob(0.0, 0.0)),
+            (1, blob(55.0, 0.0)),
+            (3, blob(-40.0, 2.0)),
+            (
+                4,
+                BlobState {
+                    health: 40.0,
+                    ..blob(20.0, 0.0)
+                },
+            ),
+        ]
+        .into_iter()
+        .collect();
+        let baseline: WorldState = baseline
+            .into_iter()
+            .chain(std::iter::once((4, blob(20.0, 0.0))))
+            .collect();
+
+        let (changed, removed) = diff(&baseline, &current);
+        assert_eq!(
+            changed,
+            vec![
+                BlobDelta {
+                    id: 1,
+                    pos: Some(PointF32::new(55.0, 0.0)),
+                    ..Default::default()
+                },
+                BlobDelta {
+                    id: 3,
+                    pos: Some(PointF32::new(-40.0, 0.0)),
+                    radius: Some(10.0),
+                    rotation: Some(2.0),
+                    owner: Some(0),
+                    health: Some(100.0),
+                    max_health: Some(100.0),
+                },
+                BlobDelta {
+                    id: 4,
+                    health: Some(40.0),
+                    ..Default::default()
+                },
+            ]
+        );
+        assert_eq!(removed, vec![2]);
+        assert_eq!(apply(&baseline, &changed, &removed), Some(current.clone()));
+
+        // A delta for a blob the client doesn't know can't be applied.
+        assert_eq!(apply(&WorldState::new(), &changed, &[]), None);
+        let (full, _) = diff(&WorldState::new(), &current);
+        assert_eq!(apply(&WorldState::new(), &full, &[]), Some(current));
+    }
+
+    #[test]
+    fn test_split_state() {
+        let current: WorldState = (0..100).map(|id| (id, blob(id as f32, 1.0))).collect();
+        let (changed, _) = diff(&WorldState::new(), &current);
+        let removed: Vec<BlobId> = (100..300).collect();
+        let projectiles: Vec<ProjectileState> = (0..50)
+            .map(|i| ProjectileState {
+                pos: PointF32::new(i as f32, 0.0),
+                velocity: PointF32::new(0.0, 800.0),
+                shooter: i,
+                owner: i,
+            })
+            .collect();
+
+        let packets = split_state(
+            7,
+            Some(3),
+            changed.clone(),
+            removed.clone(),
+            projectiles.clone(),
+            500,
+        );
+        assert!(packets.len() > 1);
+        for packet in &packets {
+            assert!(bincode::serialized_size(packet).unwrap() <= 500);
+            assert_eq!(packet.parts as usize, packets.len());
+        }
+
+        // Parts can come in any order, mixed with parts of other steps.
+        let mut assembler = StateAssembler::default();
+        let old = split_state(6, None, changed.clone(), Vec::new(), Vec::new(), 500);
+        assert_eq!(assembler.add(old[0].clone()), None);
+        for packet in packets.iter().skip(1).rev() {
+            assert_eq!(assembler.add(packet.clone()), None);
+        }
+        assert_eq!(assembler.add(old[1].clone()), None);
+        let state = assembler.add(packets[0].clone()).unwrap();
+        assert_eq!(state.step, 7);
+        assert_eq!(state.baseline, Some(3));
+        assert_eq!(state.changed, changed);
+        assert_eq!(state.removed, removed);
+        assert_eq!(state.projectiles, projectiles);
+
+        // Nothing to send is still one packet.
+        let packets = split_state(8, None, Vec::new(), Vec::new(), Vec::new(), 500);
+        assert_eq!(packets.len(), 1);
+        assert_eq!(assembler.add(packets[0].clone()).unwrap().step, 8);
+    }
+
+    #[test]
+    fn test_messages() {
+        let welcome = Welcome {
+            owner: 3,
+            token: 12345,
+            udp_port: 7000,
+            rect: Rect::new(PointF32::new(-10.0, -10.0), PointF32::new(10.0, 10.0)),
+            obstacles: vec![Rect::new(PointF32::new(1.0, 1.0), PointF32::new(2.0, 3.0))],
+            dt: 0.016,
+        };
+        let mut bytes = Vec::new();
+        write_message(&mut bytes, &welcome).unwrap();
+        write_message(&mut bytes, &welcome).unwrap();
+
+        let mut reader = &bytes[..];
+        assert_eq!(read_message::<Welcome>(&mut reader).unwrap(), welcome);
+        assert_eq!(read_message::<Welcome>(&mut reader).unwrap(), welcome);
+        assert!(read_message::<Welcome>(&mut reader).is_err());
+
+        let too_long = u32::MAX.to_le_bytes();
+        let error = read_message::<Welcome>(&mut &too_long[..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,411 @@
+use super::transport::Transport;
+use crate::simulation::blob::OwnerId;
+use crate::simulation::{Action, Event, Simulation, Snapshot};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+// Rollback networking for two players. Every frame the local inputs are
+// sent to the peer and the simulation steps right away, using a prediction
+// for the remote inputs that haven't arrived yet (the last ones we know of,
+// players tend to hold keys). When the real inputs arrive and differ from
+// the prediction, the simulation is restored to the frame they are for and
+// simulated again up to the current frame.
+//
+// Both peers have to start from the same simulation, which is made
+// deterministic so they stay the same. The simulation is passed to every
+// call instead of being owned, so the game can keep rendering it.
+
+// How many frames we run ahead of the last confirmed remote input before
+// waiting for the peer.
+pub const MAX_PREDICTION: u64 = 8;
+
+// Most frames of inputs sent in one packet, keeps packets below
+// 'MAX_PACKET_SIZE'.
+const MAX_FRAMES_PER_PACKET: u64 = 32;
+
+// How many confirmed state hashes are kept to compare with the peer's.
+const CHECKSUM_HISTORY: u64 = 128;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Packet {
+    // The sender's inputs for the frames from 'first_frame' on.
+    first_frame: u64,
+    inputs: Vec<Vec<Action>>,
+    // The receiver's inputs for all frames before 'ack' have arrived.
+    ack: u64,
+    // Step and state hash of the sender's latest confirmed state.
+    checksum: Option<(u64, u64)>,
+}
+
+pub struct RollbackSession<T: Transport> {
+    transport: T,
+    dt: f32,
+    local_owner: OwnerId,
+    remote_owner: OwnerId,
+    local_inputs: HashMap<u64, Vec<Action>>,
+    // The peer has our inputs for all frames before 'peer_ack'.
+    peer_ack: u64,
+    remote_inputs: HashMap<u64, Vec<Action>>,
+    // All remote inputs before this frame have arrived.
+    remote_confirmed: u64,
+    // Remote inputs guessed for frames that were simulated without them.
+    predictions: HashMap<u64, Vec<Action>>,
+    // State and its hash before each frame from 'remote_confirmed' on, the
+    // frames we might have to roll back to.
+    snapshots: VecDeque<(Snapshot, u64)>,
+    local_checksums: HashMap<u64, u64>,
+    remote_checksums: HashMap<u64, u64>,
+    latest_checksum: Option<(u64, u64)>,
+    desync: Option<u64>,
+    rollbacks: u64,
+}
+
+impl<T: Transport> RollbackSession<T> {
+    pub fn new(
+        simulation: &mut Simulation,
+        transport: T,
+        local_owner: OwnerId,
+        remote_owner: OwnerId,
+        dt: f32,
+    ) -> Self {
+        simulation.set_deterministic(true);
+        let frame = simulation.step();
+        RollbackSession {
+            transport,
+            dt,
+            local_owner,
+            remote_owner,
+            local_inputs: HashMap::new(),
+            peer_ack: frame,
+            remote_inputs: HashMap::new(),
+            remote_confirmed: frame,
+            predictions: HashMap::new(),
+            snapshots: VecDeque::new(),
+            local_checksums: HashMap::new(),
+            remote_checksums: HashMap::new(),
+            latest_checksum: None,
+            desync: None,
+            rollbacks: 0,
+        }
+    }
+
+    // All remote inputs before this frame are known, the simulation state
+    // at this step is final.
+    pub fn confirmed_frame(&self) -> u64 {
+        self.remote_confirmed
+    }
+
+    // The first step at which the peers' states were found to differ.
+    pub fn desync(&self) -> Option<u64> {
+        self.desync
+    }
+
+    pub fn rollbacks(&self) -> u64 {
+        self.rollbacks
+    }
+
+    // Steps the simulation one frame with 'local_actions', returns None
+    // without stepping when too far ahead of the peer. Events of frames that
+    // are simulated again after a rollback are not returned.
+    pub fn advance(
+        &mut self,
+        simulation: &mut Simulation,
+        local_actions: &[Action],
+    ) -> Option<Vec<Event>> {
+        self.poll(simulation);
+
+        let frame = simulation.step();
+        if frame >= self.remote_confirmed + MAX_PREDICTION {
+            self.send(frame);
+            return None;
+        }
+
+        self.local_inputs.insert(frame, local_actions.to_vec());
+        self.send(frame + 1);
+        self.snapshots
+            .push_back((simulation.snapshot(), simulation.state_hash()));
+        let events = self.simulate_frame(simulation, frame);
+        self.prune();
+        Some(events)
+    }
+
+    // Keeps exchanging inputs without stepping, e.g. at the end of a match
+    // until all frames are confirmed.
+    pub fn idle(&mut self, simulation: &mut Simulation) {
+        self.poll(simulation);
+        self.send(simulation.step());
+    }
+
+    // Receives the peer's packets and rolls back if needed, without
+    // stepping forward.
+    pub fn poll(&mut self, simulation: &mut Simulation) {
+        let mut rollback_frame: Option<u64> = None;
+
+        while let Some(bytes) = self.transport.receive() {
+            let packet: Packet = match bincode::deserialize(&bytes) {
+                Ok(packet) => packet,
+                Err(_) => continue,
+            };
+
+            self.peer_ack = self.peer_ack.max(packet.ack);
+            for (frame, actions) in (packet.first_frame..).zip(packet.inputs) {
+                if frame < self.remote_confirmed || self.remote_inputs.contains_key(&frame) {
+                    continue;
+                }
+                if let Some(predicted) = self.predictions.remove(&frame) {
+                    if predicted != actions {
+                        rollback_frame = Some(rollback_frame.map_or(frame, |f| f.min(frame)));
+                    }
+                }
+                self.remote_inputs.insert(frame, actions);
+            }
+            while self.remote_inputs.contains_key(&self.remote_confirmed) {
+                self.remote_confirmed += 1;
+            }
+
+            if let Some((step, hash)) = packet.checksum {
+                self.remote_checksums.insert(step, hash);
+                self.compare_checksum(step);
+            }
+        }
+
+        if let Some(frame) = rollback_frame {
+            self.rollback(simulation, frame);
+        }
+        self.confirm_snapshots();
+    }
+
+    fn prediction(&self) -> Vec<Action> {
+        match self.remote_confirmed.checked_sub(1) {
+            Some(frame) => self.remote_inputs.get(&frame).cloned().unwrap_or_default(),
+            None => Vec::new(),
+        }
+    }
+
+    fn simulate_frame(&mut self, simulation: &mut Simulation, frame: u64) -> Vec<Event> {
+        let remote_actions = match self.remote_inputs.get(&frame) {
+            Some(actions) => actions.clone(),
+            None => {
+                let predicted = self.prediction();
+                self.predictions.insert(frame, predicted.clone());
+                predicted
+            }
+        };
+
+        let mut inputs = HashMap::<OwnerId, Vec<Action>>::new();
+        inputs.insert(self.local_owner, self.local_inputs[&frame].clone());
+        inputs.insert(self.remote_owner, remote_actions);
+        simulation.simulate(&inputs, self.dt)
+    }
+
+    fn rollback(&mut self, simulation: &mut Simulation, frame: u64) {
+        let current = simulation.step();
+        let index = match self
+            .snapshots
+            .iter()
+            .position(|(snapshot, _)| snapshot.step() == frame)
+        {
+            Some(index) => index,
+            None => return,
+        };
+
+        simulation.restore(&self.snapshots[index].0);
+        self.snapshots.truncate(index);
+        for frame in frame..current {
+            self.snapshots
+                .push_back((simulation.snapshot(), simulation.state_hash()));
+            self.simulate_frame(simulation, frame);
+        }
+        self.rollbacks += 1;
+    }
+
+    // Snapshots before 'remote_confirmed' can't be rolled back to anymore,
+    // their hashes are final and can be compared with the peer's.
+    fn confirm_snapshots(&mut self) {
+        let confirmed: Vec<(u64, u64)> = self
+            .snapshots
+            .iter()
+            .filter(|(snapshot, _)| snapshot.step() <= self.remote_confirmed)
+            .map(|(snapshot, hash)| (snapshot.step(), *hash))
+            .collect();
+        for (step, hash) in confirmed {
+            if self.local_checksums.insert(step, hash).is_none() {
+                self.latest_checksum = Some((step, hash));
+                self.compare_checksum(step);
+            }
+        }
+
+        while let Some((snapshot, _)) = self.snapshots.front() {
+            if snapshot.step() >= self.remote_confirmed {
+                break;
+            }
+            self.snapshots.pop_front();
+        }
+    }
+
+    fn compare_checksum(&mut self, step: u64) {
+        if let (Some(local), Some(remote)) = (
+            self.local_checksums.get(&step),
+            self.remote_checksums.get(&step),
+        ) {
+            if local != remote && self.desync.is_none_or(|desync| step < desync) {
+                self.desync = Some(step);
+            }
+        }
+    }
+
+    fn send(&mut self, end_frame: u64) {
+        // The oldest inputs the peer is missing, it can't use later ones
+        // without them.
+        let first_frame = self.peer_ack.min(end_frame);
+        let inputs = (first_frame..end_frame)
+            .take(MAX_FRAMES_PER_PACKET as usize)
+            .map_while(|frame| self.local_inputs.get(&frame).cloned())
+            .collect();
+        let packet = Packet {
+            first_frame,
+            inputs,
+            ack: self.remote_confirmed,
+            checksum: self.latest_checksum,
+        };
+        self.transport
+            .send(&bincode::serialize(&packet).expect("Cannot serialize packet"));
+    }
+
+    fn prune(&mut self) {
+        // Local inputs are kept until the peer has them and we can't roll
+        // back to them anymore.
+        let local_needed = self.peer_ack.min(self.remote_confirmed);
+        self.local_inputs.retain(|frame, _| *frame >= local_needed);
+        // The last confirmed remote input is the prediction.
+        let remote_needed = self.remote_confirmed.saturating_sub(1);
+        self.remote_inputs
+            .retain(|frame, _| *frame >= remote_needed);
+        let checksums_needed = self.remote_confirmed.saturating_sub(CHECKSUM_HISTORY);
+        self.local_checksums
+            .retain(|step, _| *step >= checksums_needed);
+        self.remote_checksums
+            .retain(|step, _| *step >= checksums_needed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::InputEvent;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // Packets with the time they arrive.
+    type Queue = Rc<RefCell<VecDeque<(u64, Vec<u8>)>>>;
+
+    // One end of an in-memory link, packets take 'latency' calls to
+    // 'receive' to arrive.
+    struct Link {
+        queue: Queue,
+        incoming: Queue,
+        time: u64,
+        latency: u64,
+    }
+
+    fn link_pair(latency: u64) -> (Link, Link) {
+        let first = Queue::default();
+        let second = Queue::default();
+        (
+            Link {
+                queue: first.clone(),
+                incoming: second.clone(),
+                time: 0,
+                latency,
+            },
+            Link {
+                queue: second,
+                incoming: first,
+                time: 0,
+                latency,
+            },
+        )
+    }
+
+    impl Transport for Link {
+        fn send(&mut self, packet: &[u8]) {
+            self.queue
+                .borrow_mut()
+                .push_back((self.time + self.latency, packet.to_vec()));
+        }
+
+        fn receive(&mut self) -> Option<Vec<u8>> {
+            let mut incoming = self.incoming.borrow_mut();
+            match incoming.front() {
+                Some((due, _)) if *due <= self.time => incoming.pop_front().map(|(_, p)| p),
+                _ => {
+                    self.time += 1;
+                    None
+                }
+            }
+        }
+    }
+
+    fn actions(owner: OwnerId, frame: u64) -> Vec<Action> {
+        // Changes every few frames, so predictions are often wrong.
+        match (frame / 7 + owner as u64) % 3 {
+            0 => vec![Action::new(InputEvent::Forward, 1.0)],
+            1 => vec![Action::new(InputEvent::TurnLeft, 1.0)],
+            _ => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_peers_agree_after_rollbacks() {
+        let dt = 1.0 / 60.0;
+        let frames = 200;
+        let (first_link, second_link) = link_pair(3);
+        let mut simulations = [Simulation::new(), Simulation::new()];
+        let [first_simulation, second_simulation] = &mut simulations;
+        let mut first = RollbackSession::new(first_simulation, first_link, 0, 1, dt);
+        let mut second = RollbackSession::new(second_simulation, second_link, 1, 0, dt);
+
+        while first_simulation.step() < frames || second_simulation.step() < frames {
+            if first_simulation.step() < frames {
+                let frame = first_simulation.step();
+                first.advance(first_simulation, &actions(0, frame));
+            }
+            if second_simulation.step() < frames {
+                let frame = second_simulation.step();
+                second.advance(second_simulation, &actions(1, frame));
+            }
+        }
+        while first.confirmed_frame() < frames || second.confirmed_frame() < frames {
+            first.idle(first_simulation);
+            second.idle(second_simulation);
+        }
+
+        let mut reference = Simulation::new();
+        reference.set_deterministic(true);
+        for frame in 0..frames {
+            let mut inputs = HashMap::new();
+            inputs.insert(0, actions(0, frame));
+            inputs.insert(1, actions(1, frame));
+            reference.simulate(&inputs, dt);
+        }
+
+        assert!(first.rollbacks() > 0 && second.rollbacks() > 0);
+        assert_eq!(first_simulation.state_hash(), reference.state_hash());
+        assert_eq!(second_simulation.state_hash(), reference.state_hash());
+        assert_eq!(first.desync(), None);
+        assert_eq!(second.desync(), None);
+    }
+
+    #[test]
+    fn test_waits_for_peer() {
+        let (link, _other) = link_pair(0);
+        let mut simulation = Simulation::new();
+        let mut session = RollbackSession::new(&mut simulation, link, 0, 1, 1.0 / 60.0);
+
+        for _ in 0..MAX_PREDICTION {
+            assert!(session.advance(&mut simulation, &[]).is_some());
+        }
+        assert!(session.advance(&mut simulation, &[]).is_none());
+        assert_eq!(simulation.step(), MAX_PREDICTION);
+    }
+}
@@ -0,0 +1,255 @@
+use super::protocol::{self, InputPacket, Welcome, WorldState, MAX_STATE_PACKET};
+use crate::math::point::PointF32;
+use crate::simulation::blob::{BlobDesc, OwnerId};
+use crate::simulation::{Action, Event, Simulation};
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Read};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
+
+// How many past states are kept to send deltas against.
+const STATE_HISTORY: usize = 64;
+
+struct ServerClient {
+    stream: TcpStream,
+    owner: OwnerId,
+    token: u64,
+    // Where the client's inputs come from, states are sent back there.
+    addr: Option<SocketAddr>,
+    sequence: u64,
+    actions: Vec<Action>,
+    ack: Option<u64>,
+}
+
+// Owns the simulation of an arena that any number of clients can join,
+// each client controls the blobs of one owner.
+pub struct Server {
+    simulation: Simulation,
+    listener: TcpListener,
+    socket: UdpSocket,
+    clients: Vec<ServerClient>,
+    history: VecDeque<(u64, WorldState)>,
+    random: RandomState,
+    send_error: Option<io::Error>,
+}
+
+impl Server {
+    // Listens for clients over TCP on 'addr', inputs and states go over UDP
+    // on the same IP.
+    pub fn bind(addr: impl ToSocketAddrs, simulation: Simulation) -> io::Result<Server> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let socket = UdpSocket::bind(SocketAddr::new(listener.local_addr()?.ip(), 0))?;
+        socket.set_nonblocking(true)?;
+        Ok(Server {
+            simulation,
+            listener,
+            socket,
+            clients: Vec::new(),
+            history: VecDeque::new(),
+            random: RandomState::new(),
+            send_error: None,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn simulation(&self) -> &Simulation {
+        &self.simulation
+    }
+
+    // For changing the arena while clients play in it.
+    pub fn simulation_mut(&mut self) -> &mut Simulation {
+        &mut self.simulation
+    }
+
+    pub fn client_count(&self) -> usize {
+        self.clients.len()
+    }
+
+    // The last error sending a state to a client since the previous call.
+    pub fn take_send_error(&mut self) -> Option<io::Error> {
+        self.send_error.take()
+    }
+
+    pub fn tick(&mut self, dt: f32) -> Vec<Event> {
+        self.accept_clients(dt);
+        self.drop_disconnected();
+        self.respawn_dead();
+        self.receive_inputs();
+
+        let inputs: HashMap<OwnerId, Vec<Action>> = self
+            .clients
+            .iter()
+            .map(|client| (client.owner, client.actions.clone()))
+            .collect();
+        let events = self.simulation.simulate(&inputs, dt);
+
+        self.send_states();
+        events
+    }
+
+    // New blobs are placed on a spiral around the center of the arena, at
+    // the first spot that is free.
+    fn spawn_position(&self, radius: f32) -> PointF32 {
+        let center = self.simulation.rect().center();
+        (0..)
+            .map(|i| {
+                let angle = i as f32 * 2.4;
+                let distance = 4.0 * radius * (i as f32).sqrt();
+                center + PointF32::new(angle.cos(), angle.sin()) * distance
+            })
+            .find(|pos| {
+                self.simulation.blobs().iter().all(|blob| {
+                    (blob.circle.pos - *pos).magnitude() > blob.circle.radius + radius * 2.0
+                })
+            })
+            .unwrap()
+    }
+
+    fn accept_clients(&mut self, dt: f32) {
+        while let Ok((mut stream, _)) = self.listener.accept() {
+            let radius = 10.0;
+            let pos = self.spawn_position(radius);
+            // The first blob of a client is its own owner, the owner id is
+            // then unique as long as the client is connected.
+            let owner = self
+                .simulation
+                .spawn_blob(&BlobDesc::new(pos.x, pos.y, radius));
+
+            let mut hasher = self.random.build_hasher();
+            hasher.write_u32(owner);
+            let token = hasher.finish();
+
+            let welcome = Welcome {
+                owner,
+                token,
+                udp_port: self.socket.local_addr().map_or(0, |addr| addr.port()),
+                rect: self.simulation.rect(),
+                obstacles: self.simulation.obstacles().to_vec(),
+                dt,
+            };
+            let connected = protocol::write_message(&mut stream, &welcome)
+                .and_then(|_| stream.set_nonblocking(true));
+            if connected.is_err() {
+                self.simulation.despawn(owner);
+                continue;
+            }
+
+            self.clients.push(ServerClient {
+                stream,
+                owner,
+                token,
+                addr: None,
+                sequence: 0,
+                actions: Vec::new(),
+                ack: None,
+            });
+        }
+    }
+
+    fn drop_disconnected(&mut self) {
+        let simulation = &mut self.simulation;
+        self.clients.retain(|client| {
+            // Clients don't send anything over TCP, reading only tells if
+            // the connection is still open.
+            let mut buffer = [0u8; 64];
+            let connected = match (&client.stream).read(&mut buffer) {
+                Ok(0) => false,
+                Ok(_) => true,
+                Err(error) => error.kind() == io::ErrorKind::WouldBlock,
+            };
+            if !connected {
+                for blob_id in simulation.owned_blobs(client.owner) {
+                    simulation.despawn(blob_id);
+                }
+            }
+            connected
+        });
+    }
+
+    // A client whose blobs were all killed gets a new one, with the same
+    // owner so its token stays valid.
+    fn respawn_dead(&mut self) {
+        for index in 0..self.clients.len() {
+            let owner = self.clients[index].owner;
+            if !self.simulation.owned_blobs(owner).is_empty() {
+                continue;
+            }
+            let radius = 10.0;
+            let pos = self.spawn_position(radius);
+            let mut desc = BlobDesc::new(pos.x, pos.y, radius);
+            desc.owner = Some(owner);
+            self.simulation.spawn_blob(&desc);
+        }
+    }
+
+    fn receive_inputs(&mut self) {
+        let mut buffer = [0u8; 2048];
+        while let Ok((size, from)) = self.socket.recv_from(&mut buffer) {
+            let packet: InputPacket = match bincode::deserialize(&buffer[..size]) {
+                Ok(packet) => packet,
+                Err(_) => continue,
+            };
+            let client = self
+                .clients
+                .iter_mut()
+                .find(|client| client.owner == packet.owner && client.token == packet.token);
+            if let Some(client) = client {
+                if packet.sequence > client.sequence {
+                    client.sequence = packet.sequence;
+                    client.actions = packet.actions;
+                    client.ack = packet.ack;
+                    client.addr = Some(from);
+                }
+            }
+        }
+    }
+
+    fn send_states(&mut self) {
+        let step = self.simulation.step();
+        let current = protocol::world_state(&self.simulation);
+        let projectiles = protocol::projectile_states(&self.simulation);
+        let empty = WorldState::new();
+
+        for client in &self.clients {
+            let addr = match client.addr {
+                Some(addr) => addr,
+                None => continue,
+            };
+            // Deltas against the latest state the client has, or the full
+            // state if we don't have that one anymore.
+            let baseline = client.ack.and_then(|ack| {
+                self.history
+                    .iter()
+                    .find(|(history_step, _)| *history_step == ack)
+            });
+            let (changed, removed) =
+                protocol::diff(baseline.map_or(&empty, |(_, state)| state), &current);
+            let packets = protocol::split_state(
+                step,
+                baseline.map(|(baseline_step, _)| *baseline_step),
+                changed,
+                removed,
+                projectiles.clone(),
+                MAX_STATE_PACKET,
+            );
+            for packet in packets {
+                let sent = bincode::serialize(&packet)
+                    .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+                    .and_then(|bytes| self.socket.send_to(&bytes, addr));
+                if let Err(error) = sent {
+                    self.send_error = Some(error);
+                }
+            }
+        }
+
+        self.history.push_back((step, current));
+        if self.history.len() > STATE_HISTORY {
+            self.history.pop_front();
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use crate::math::random::xorshift;
+use std::collections::VecDeque;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+// An unreliable way to send packets to the other player, packets may be
+// lost, duplicated or arrive out of order.
+pub trait Transport {
+    fn send(&mut self, packet: &[u8]);
+    // Returns the next received packet, without blocking.
+    fn receive(&mut self) -> Option<Vec<u8>>;
+}
+
+// Largest packet we send, comfortably below the usual MTU.
+pub const MAX_PACKET_SIZE: usize = 1200;
+
+pub struct UdpTransport {
+    socket: UdpSocket,
+    peer: SocketAddr,
+}
+
+impl UdpTransport {
+    pub fn new(local: impl ToSocketAddrs, peer: impl ToSocketAddrs) -> io::Result<Self> {
+        UdpTransport::from_socket(UdpSocket::bind(local)?, peer)
+    }
+
+    pub fn from_socket(socket: UdpSocket, peer: impl ToSocketAddrs) -> io::Result<Self> {
+        socket.set_nonblocking(true)?;
+        let peer = peer
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no peer address"))?;
+        Ok(UdpTransport { socket, peer })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+}
+
+impl Transport for UdpTransport {
+    fn send(&mut self, packet: &[u8]) {
+        // A packet that can't be sent is just lost, the rollback layer
+        // sends its inputs again until they are acknowledged.
+        let _ = self.socket.send_to(packet, self.peer);
+    }
+
+    fn receive(&mut self) -> Option<Vec<u8>> {
+        let mut buffer = [0u8; MAX_PACKET_SIZE];
+        loop {
+            match self.socket.recv_from(&mut buffer) {
+                // Anyone could send to the socket, only the peer is heard.
+                Ok((size, from)) if from == self.peer => return Some(buffer[..size].to_vec()),
+                Ok(_) => continue,
+                // WouldBlock when there is nothing to read, other errors
+                // (e.g. the peer not listening yet) are treated the same.
+                Err(_) => return None,
+            }
+        }
+    }
+}
+
+// Wraps a transport to make the network worse than it is, outgoing packets
+// are delayed by 'latency' and dropped with probability 'loss'. Used to
+// test over loopback.
+pub struct LinkConditioner<T: Transport> {
+    transport: T,
+    latency: Duration,
+    loss: f32,
+    random_state: u64,
+    delayed: VecDeque<(Instant, Vec<u8>)>,
+}
+
+impl<T: Transport> LinkConditioner<T> {
+    pub fn new(transport: T, latency: Duration, loss: f32, seed: u64) -> Self {
+        LinkConditioner {
+            transport,
+            latency,
+            loss,
+            // Xorshift doesn't work with a zero state.
+            random_state: seed | 1,
+            delayed: VecDeque::new(),
+        }
+    }
+
+    fn send_due(&mut self) {
+        let now = Instant::now();
+        while let Some((due, _)) = self.delayed.front() {
+            if *due > now {
+                break;
+            }
+            let (_, packet) = self.delayed.pop_front().unwrap();
+            self.transport.send(&packet);
+        }
+    }
+}
+
+impl<T: Transport> Transport for LinkConditioner<T> {
+    fn send(&mut self, packet: &[u8]) {
+        if xorshift(&mut self.random_state) >= self.loss {
+            self.delayed
+                .push_back((Instant::now() + self.latency, packet.to_vec()));
+        }
+        self.send_due();
+    }
+
+    fn receive(&mut self) -> Option<Vec<u8>> {
+        self.send_due();
+        self.transport.receive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_udp_loopback() {
+        let first_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let second_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let first_addr = first_socket.local_addr().unwrap();
+        let mut first =
+            UdpTransport::from_socket(first_socket, second_socket.local_addr().unwrap()).unwrap();
+        let mut second = UdpTransport::from_socket(second_socket, first_addr).unwrap();
+
+        assert_eq!(first.receive(), None);
+        second.send(b"hello");
+        let started = Instant::now();
+        let mut received = None;
+        while received.is_none() && started.elapsed() < Duration::from_secs(1) {
+            received = first.receive();
+        }
+        assert_eq!(received.unwrap(), b"hello".to_vec());
+    }
+
+    struct Counter {
+        sent: usize,
+    }
+
+    impl Transport for Counter {
+        fn send(&mut self, _packet: &[u8]) {
+            self.sent += 1;
+        }
+
+        fn receive(&mut self) -> Option<Vec<u8>> {
+            None
+        }
+    }
+
+    #[test]
+    fn test_link_conditioner() {
+        let mut lossy = LinkConditioner::new(Counter { sent: 0 }, Duration::from_secs(0), 0.25, 7);
+        for _ in 0..1000 {
+            lossy.send(b"packet");
+        }
+        let sent = lossy.transport.sent;
+        assert!(sent > 700 && sent < 800, "{} packets sent", sent);
+
+        let mut slow = LinkConditioner::new(Counter { sent: 0 }, Duration::from_secs(60), 0.0, 7);
+        slow.send(b"packet");
+        slow.receive();
+        assert_eq!(slow.transport.sent, 0);
+    }
+}
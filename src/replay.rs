@@ -0,0 +1,567 @@
+use crate::math::point::PointF32;
+use crate::math::rect::Rect;
+use crate::simulation::blob::{BlobDesc, OwnerId};
+use crate::simulation::{Action, InputEvent, Simulation, Tuning};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{Read, Write};
+use std::path::Path;
+
+// A recorded game, the setup of the simulation followed by the inputs of
+// every step. Played back in a deterministic simulation it gives the same
+// game bit for bit.
+//
+// The file is little endian, 'varint' is an unsigned LEB128:
+//
+// "BLBRPLAY" version: u8
+// dt: f32 deterministic: u8 arena: 4 x f32 wall restitution: f32
+// damage threshold: f32 damage scale: f32
+// thrust: f32 angle thrust: f32 drag: f32 angular drag: f32
+// food: varint food seed: u64
+// obstacle count: varint, per obstacle 4 x f32
+// blob count: varint, per blob x, y, radius, mass, rotation, health: f32
+// owner: varint, 0 if the blob is its own owner and otherwise owner + 1
+//
+// and then runs until the end of the file:
+//
+// step count: varint action count: varint
+// per action owner: varint event: u8 value: f32
+//
+// where a run is a number of consecutive steps with the same inputs, so
+// holding a key for a while only takes a few bytes.
+
+const MAGIC: &[u8; 8] = b"BLBRPLAY";
+const VERSION: u8 = 7;
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(std::io::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::Io(error) => write!(f, "{}", error),
+            ReplayError::Invalid(message) => write!(f, "invalid replay: {}", message),
+        }
+    }
+}
+
+impl From<std::io::Error> for ReplayError {
+    fn from(error: std::io::Error) -> Self {
+        ReplayError::Io(error)
+    }
+}
+
+// How the simulation looked before the first step.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ReplaySetup {
+    pub dt: f32,
+    pub deterministic: bool,
+    pub rect: Rect,
+    pub wall_restitution: f32,
+    pub damage: (f32, f32),
+    pub tuning: Tuning,
+    // Number of pellets and the seed they are placed from.
+    pub food: (usize, u64),
+    pub obstacles: Vec<Rect>,
+    // In id order, so spawning them in order gives the same ids again.
+    pub blobs: Vec<BlobDesc>,
+}
+
+impl ReplaySetup {
+    // 'simulation' should not have been stepped yet, velocities and forces
+    // are not part of the setup.
+    pub fn from_simulation(simulation: &Simulation, dt: f32) -> Self {
+        let mut blobs: Vec<_> = simulation.blobs().iter().collect();
+        blobs.sort_by_key(|blob| blob.id);
+        ReplaySetup {
+            dt,
+            deterministic: simulation.is_deterministic(),
+            rect: simulation.rect(),
+            wall_restitution: simulation.wall_restitution(),
+            damage: simulation.damage(),
+            tuning: simulation.tuning(),
+            food: simulation.food_settings(),
+            obstacles: simulation.obstacles().to_vec(),
+            blobs: blobs
+                .iter()
+                .map(|blob| BlobDesc {
+                    pos: blob.circle.pos,
+                    radius: blob.circle.radius,
+                    mass: blob.mass,
+                    rotation: blob.rotation,
+                    health: blob.health,
+                    owner: Some(blob.owner),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn simulation(&self) -> Simulation {
+        let mut simulation = Simulation::new_empty(self.rect);
+        simulation.set_deterministic(self.deterministic);
+        simulation.set_wall_restitution(self.wall_restitution);
+        simulation.set_damage(self.damage.0, self.damage.1);
+        simulation.set_tuning(self.tuning);
+        for obstacle in &self.obstacles {
+            simulation.add_obstacle(*obstacle);
+        }
+        simulation.set_food(self.food.0, self.food.1);
+        for desc in &self.blobs {
+            simulation.spawn_blob(desc);
+        }
+        simulation
+    }
+}
+
+// The inputs of one step, sorted by owner. The order of the actions of an
+// owner is kept.
+type StepActions = Vec<(OwnerId, Action)>;
+
+fn step_actions(inputs: &HashMap<OwnerId, Vec<Action>>) -> StepActions {
+    let mut actions: StepActions = inputs
+        .iter()
+        .flat_map(|(owner, actions)| actions.iter().map(move |action| (*owner, *action)))
+        .collect();
+    actions.sort_by_key(|(owner, _)| *owner);
+    actions
+}
+
+fn event_code(event: InputEvent) -> u8 {
+    match event {
+        InputEvent::Forward => 0,
+        InputEvent::Backward => 1,
+        InputEvent::TurnLeft => 2,
+        InputEvent::TurnRight => 3,
+        InputEvent::Split => 4,
+        InputEvent::Fire => 5,
+    }
+}
+
+fn event_from_code(code: u8) -> Option<InputEvent> {
+    match code {
+        0 => Some(InputEvent::Forward),
+        1 => Some(InputEvent::Backward),
+        2 => Some(InputEvent::TurnLeft),
+        3 => Some(InputEvent::TurnRight),
+        4 => Some(InputEvent::Split),
+        5 => Some(InputEvent::Fire),
+        _ => None,
+    }
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn write_f32(bytes: &mut Vec<u8>, value: f32) {
+    bytes.extend_from_slice(&value.to_le_bytes());
+}
+
+fn encode_setup(setup: &ReplaySetup) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.push(VERSION);
+    write_f32(&mut bytes, setup.dt);
+    bytes.push(setup.deterministic as u8);
+    for value in &[
+        setup.rect.bottom_left.x,
+        setup.rect.bottom_left.y,
+        setup.rect.top_right.x,
+        setup.rect.top_right.y,
+        setup.wall_restitution,
+        setup.damage.0,
+        setup.damage.1,
+        setup.tuning.thrust,
+        setup.tuning.angle_thrust,
+        setup.tuning.drag,
+        setup.tuning.angular_drag,
+    ] {
+        write_f32(&mut bytes, *value);
+    }
+    write_varint(&mut bytes, setup.food.0 as u64);
+    bytes.extend_from_slice(&setup.food.1.to_le_bytes());
+    write_varint(&mut bytes, setup.obstacles.len() as u64);
+    for obstacle in &setup.obstacles {
+        for value in &[
+            obstacle.bottom_left.x,
+            obstacle.bottom_left.y,
+            obstacle.top_right.x,
+            obstacle.top_right.y,
+        ] {
+            write_f32(&mut bytes, *value);
+        }
+    }
+    write_varint(&mut bytes, setup.blobs.len() as u64);
+    for desc in &setup.blobs {
+        for value in &[
+            desc.pos.x,
+            desc.pos.y,
+            desc.radius,
+            desc.mass,
+            desc.rotation,
+            desc.health,
+        ] {
+            write_f32(&mut bytes, *value);
+        }
+        write_varint(&mut bytes, desc.owner.map_or(0, |owner| owner as u64 + 1));
+    }
+    bytes
+}
+
+fn encode_run(steps: u64, actions: &[(OwnerId, Action)]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    write_varint(&mut bytes, steps);
+    write_varint(&mut bytes, actions.len() as u64);
+    for (owner, action) in actions {
+        write_varint(&mut bytes, *owner as u64);
+        bytes.push(event_code(action.event));
+        write_f32(&mut bytes, action.value);
+    }
+    bytes
+}
+
+// Writes a replay while the game is running. A run is written when the
+// inputs change, the last one when finishing or dropping the writer, so
+// most of a game is kept even if it ends with a panic.
+pub struct ReplayWriter<W: Write> {
+    writer: W,
+    run: Option<(u64, StepActions)>,
+}
+
+impl<W: Write> ReplayWriter<W> {
+    pub fn new(mut writer: W, setup: &ReplaySetup) -> std::io::Result<Self> {
+        writer.write_all(&encode_setup(setup))?;
+        Ok(ReplayWriter { writer, run: None })
+    }
+
+    pub fn record(&mut self, inputs: &HashMap<OwnerId, Vec<Action>>) -> std::io::Result<()> {
+        let actions = step_actions(inputs);
+        match &mut self.run {
+            Some((steps, run_actions)) if *run_actions == actions => *steps += 1,
+            _ => {
+                self.write_run()?;
+                self.run = Some((1, actions));
+            }
+        }
+        Ok(())
+    }
+
+    fn write_run(&mut self) -> std::io::Result<()> {
+        if let Some((steps, actions)) = self.run.take() {
+            self.writer.write_all(&encode_run(steps, &actions))?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        self.write_run()?;
+        self.writer.flush()
+    }
+}
+
+impl<W: Write> Drop for ReplayWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn at_end(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    fn truncated(&self) -> ReplayError {
+        ReplayError::Invalid(format!("truncated at byte {}", self.pos))
+    }
+
+    fn bytes(&mut self, count: usize) -> Result<&'a [u8], ReplayError> {
+        if self.bytes.len() - self.pos < count {
+            return Err(self.truncated());
+        }
+        let bytes = &self.bytes[self.pos..self.pos + count];
+        self.pos += count;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, ReplayError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn f32(&mut self) -> Result<f32, ReplayError> {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(self.bytes(4)?);
+        Ok(f32::from_le_bytes(bytes))
+    }
+
+    fn u64(&mut self) -> Result<u64, ReplayError> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    fn varint(&mut self) -> Result<u64, ReplayError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(ReplayError::Invalid(format!(
+            "varint too long at byte {}",
+            self.pos
+        )))
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+struct ReplayRun {
+    first_step: u64,
+    steps: u64,
+    actions: StepActions,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Replay {
+    pub setup: ReplaySetup,
+    runs: Vec<ReplayRun>,
+}
+
+impl Replay {
+    pub fn read(mut reader: impl Read) -> Result<Replay, ReplayError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let mut decoder = Decoder {
+            bytes: &bytes,
+            pos: 0,
+        };
+
+        if decoder.bytes(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return Err(ReplayError::Invalid("not a replay file".to_string()));
+        }
+        let version = decoder.u8()?;
+        if version != VERSION {
+            return Err(ReplayError::Invalid(format!(
+                "unsupported version {}",
+                version
+            )));
+        }
+
+        let dt = decoder.f32()?;
+        let deterministic = decoder.u8()? != 0;
+        let bottom_left = PointF32::new(decoder.f32()?, decoder.f32()?);
+        let top_right = PointF32::new(decoder.f32()?, decoder.f32()?);
+        let wall_restitution = decoder.f32()?;
+        let damage = (decoder.f32()?, decoder.f32()?);
+        let tuning = Tuning {
+            thrust: decoder.f32()?,
+            angle_thrust: decoder.f32()?,
+            drag: decoder.f32()?,
+            angular_drag: decoder.f32()?,
+        };
+        let food = (decoder.varint()? as usize, decoder.u64()?);
+        let obstacle_count = decoder.varint()?;
+        let mut obstacles = Vec::new();
+        for _ in 0..obstacle_count {
+            let bottom_left = PointF32::new(decoder.f32()?, decoder.f32()?);
+            let top_right = PointF32::new(decoder.f32()?, decoder.f32()?);
+            obstacles.push(Rect::new(bottom_left, top_right));
+        }
+        let blob_count = decoder.varint()?;
+        let mut blobs = Vec::new();
+        for _ in 0..blob_count {
+            let pos = PointF32::new(decoder.f32()?, decoder.f32()?);
+            let radius = decoder.f32()?;
+            let mass = decoder.f32()?;
+            let rotation = decoder.f32()?;
+            let health = decoder.f32()?;
+            let owner = match decoder.varint()? {
+                0 => None,
+                owner => Some((owner - 1) as OwnerId),
+            };
+            blobs.push(BlobDesc {
+                pos,
+                radius,
+                mass,
+                rotation,
+                health,
+                owner,
+            });
+        }
+        let setup = ReplaySetup {
+            dt,
+            deterministic,
+            rect: Rect::new(bottom_left, top_right),
+            wall_restitution,
+            damage,
+            tuning,
+            food,
+            obstacles,
+            blobs,
+        };
+
+        let mut runs = Vec::new();
+        let mut first_step = 0u64;
+        while !decoder.at_end() {
+            let steps = decoder.varint()?;
+            let action_count = decoder.varint()?;
+            let mut actions = StepActions::new();
+            for _ in 0..action_count {
+                let owner = decoder.varint()? as OwnerId;
+                let code = decoder.u8()?;
+                let event = event_from_code(code).ok_or_else(|| {
+                    ReplayError::Invalid(format!(
+                        "unknown event {} at byte {}",
+                        code,
+                        decoder.pos - 1
+                    ))
+                })?;
+                let value = decoder.f32()?;
+                actions.push((owner, Action::new(event, value)));
+            }
+            runs.push(ReplayRun {
+                first_step,
+                steps,
+                actions,
+            });
+            first_step += steps;
+        }
+
+        Ok(Replay { setup, runs })
+    }
+
+    pub fn load(path: &Path) -> Result<Replay, ReplayError> {
+        Replay::read(std::fs::File::open(path)?)
+    }
+
+    pub fn step_count(&self) -> u64 {
+        self.runs.last().map_or(0, |run| run.first_step + run.steps)
+    }
+
+    pub fn inputs(&self, step: u64) -> HashMap<OwnerId, Vec<Action>> {
+        let mut inputs = HashMap::<OwnerId, Vec<Action>>::new();
+        let index = self
+            .runs
+            .partition_point(|run| run.first_step + run.steps <= step);
+        if let Some(run) = self.runs.get(index) {
+            for (owner, action) in &run.actions {
+                inputs.entry(*owner).or_default().push(*action);
+            }
+        }
+        inputs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inputs(actions: &[(OwnerId, InputEvent, f32)]) -> HashMap<OwnerId, Vec<Action>> {
+        let mut inputs = HashMap::<OwnerId, Vec<Action>>::new();
+        for (owner, event, value) in actions {
+            inputs
+                .entry(*owner)
+                .or_default()
+                .push(Action::new(*event, *value));
+        }
+        inputs
+    }
+
+    fn record(setup: &ReplaySetup, steps: &[HashMap<OwnerId, Vec<Action>>]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut writer = ReplayWriter::new(&mut bytes, setup).unwrap();
+        for step in steps {
+            writer.record(step).unwrap();
+        }
+        drop(writer);
+        bytes
+    }
+
+    #[test]
+    fn test_write_read() {
+        let setup = ReplaySetup::from_simulation(&Simulation::new(), 1.0 / 60.0);
+        let forward = inputs(&[(0, InputEvent::Forward, 1.0)]);
+        let both = inputs(&[
+            (1, InputEvent::TurnLeft, 0.5),
+            (0, InputEvent::Backward, 1.0),
+            (0, InputEvent::TurnRight, 2.0),
+        ]);
+        let mut steps = vec![HashMap::new(); 3];
+        steps.extend(vec![forward.clone(); 100]);
+        steps.push(both.clone());
+
+        let bytes = record(&setup, &steps);
+        // Four runs after the setup.
+        assert!(bytes.len() < encode_setup(&setup).len() + 40);
+
+        let replay = Replay::read(&bytes[..]).unwrap();
+        assert_eq!(replay.setup, setup);
+        assert_eq!(replay.step_count(), 104);
+        assert!(replay.inputs(0).is_empty());
+        assert_eq!(replay.inputs(3), forward);
+        assert_eq!(replay.inputs(102), forward);
+        assert_eq!(replay.inputs(103), both);
+        assert!(replay.inputs(104).is_empty());
+    }
+
+    #[test]
+    fn test_replay_gives_same_game() {
+        let dt = 1.0 / 60.0;
+        let mut simulation = Simulation::new();
+        simulation.set_deterministic(true);
+        let mut tuning = simulation.tuning();
+        tuning.thrust *= 2.0;
+        simulation.set_tuning(tuning);
+        let setup = ReplaySetup::from_simulation(&simulation, dt);
+
+        let steps: Vec<_> = (0..200)
+            .map(|step| {
+                if step % 50 < 30 {
+                    inputs(&[
+                        (0, InputEvent::Forward, 1.0),
+                        (1, InputEvent::TurnLeft, 1.0),
+                    ])
+                } else {
+                    inputs(&[(1, InputEvent::Forward, 1.0)])
+                }
+            })
+            .collect();
+        for step in &steps {
+            simulation.simulate(step, dt);
+        }
+
+        let replay = Replay::read(&record(&setup, &steps)[..]).unwrap();
+        let mut replayed = replay.setup.simulation();
+        for step in 0..replay.step_count() {
+            replayed.simulate(&replay.inputs(step), replay.setup.dt);
+        }
+
+        assert_eq!(replayed.state_hash(), simulation.state_hash());
+    }
+
+    #[test]
+    fn test_read_errors() {
+        let setup = ReplaySetup::from_simulation(&Simulation::new(), 1.0 / 60.0);
+        let bytes = record(&setup, &[inputs(&[(0, InputEvent::Forward, 1.0)])]);
+
+        assert!(Replay::read(&b"not a replay"[..]).is_err());
+        assert!(Replay::read(&bytes[..bytes.len() - 1]).is_err());
+
+        let mut unknown_event = bytes.clone();
+        let event_index = bytes.len() - 5;
+        unknown_event[event_index] = 9;
+        assert!(Replay::read(&unknown_event[..]).is_err());
+    }
+}
@@ -0,0 +1,35 @@
+use std::fmt;
+
+// Reading the settings files that are written in RON, like levels and the
+// tuning.
+
+#[derive(Debug)]
+pub enum RonError {
+    Io(std::io::Error),
+    // 'line' is 1-based, 0 if the problem isn't on a single line.
+    Invalid { line: usize, message: String },
+}
+
+impl fmt::Display for RonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RonError::Io(error) => write!(f, "{}", error),
+            RonError::Invalid { line: 0, message } => write!(f, "{}", message),
+            RonError::Invalid { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+pub fn invalid(message: String) -> RonError {
+    RonError::Invalid { line: 0, message }
+}
+
+pub fn from_ron<T: serde::de::DeserializeOwned>(source: &str) -> Result<T, RonError> {
+    // Optional settings can be written without 'Some(...)'.
+    let options =
+        ron::Options::default().with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME);
+    options.from_str(source).map_err(|error| RonError::Invalid {
+        line: error.position.line,
+        message: error.code.to_string(),
+    })
+}
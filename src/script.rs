@@ -0,0 +1,165 @@
+use crate::simulation::{blob::OwnerId, Action, InputEvent};
+use serde::de::IntoDeserializer;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+
+// Scripted inputs, one action per line:
+//
+// # <steps> <owner> <event> [value]
+// 0..119 0 Forward
+// 60 1 TurnLeft 0.5
+//
+// where steps is a single step or an inclusive range of steps.
+
+#[derive(Debug, PartialEq)]
+pub struct ScriptError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+struct ScriptEntry {
+    first_step: u64,
+    last_step: u64,
+    owner: OwnerId,
+    action: Action,
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Script {
+    entries: Vec<ScriptEntry>,
+}
+
+fn parse_steps(steps: &str) -> Option<(u64, u64)> {
+    match steps.find("..") {
+        Some(index) => {
+            let first = steps[..index].parse().ok()?;
+            let last = steps[index + 2..].parse().ok()?;
+            if first <= last {
+                Some((first, last))
+            } else {
+                None
+            }
+        }
+        None => {
+            let step = steps.parse().ok()?;
+            Some((step, step))
+        }
+    }
+}
+
+impl Script {
+    pub fn parse(source: &str) -> Result<Script, ScriptError> {
+        let mut entries = Vec::<ScriptEntry>::new();
+
+        for (index, line) in source.lines().enumerate() {
+            let error = |message: String| ScriptError {
+                line: index + 1,
+                message,
+            };
+
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 3 || fields.len() > 4 {
+                return Err(error(format!(
+                    "expected '<steps> <owner> <event> [value]', got '{}'",
+                    line
+                )));
+            }
+
+            let (first_step, last_step) = parse_steps(fields[0])
+                .ok_or_else(|| error(format!("invalid steps '{}'", fields[0])))?;
+            let owner = fields[1]
+                .parse::<OwnerId>()
+                .map_err(|_| error(format!("invalid owner '{}'", fields[1])))?;
+            let event = InputEvent::deserialize(fields[2].into_deserializer())
+                .map_err(|e: serde::de::value::Error| error(e.to_string()))?;
+            let value = match fields.get(3) {
+                Some(value) => value
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|value| value.is_finite())
+                    .ok_or_else(|| error(format!("invalid value '{}'", value)))?,
+                None => 1.0,
+            };
+
+            entries.push(ScriptEntry {
+                first_step,
+                last_step,
+                owner,
+                action: Action::new(event, value),
+            });
+        }
+
+        Ok(Script { entries })
+    }
+
+    pub fn last_step(&self) -> Option<u64> {
+        self.entries.iter().map(|entry| entry.last_step).max()
+    }
+
+    pub fn inputs(&self, step: u64) -> HashMap<OwnerId, Vec<Action>> {
+        let mut inputs = HashMap::<OwnerId, Vec<Action>>::new();
+        for entry in &self.entries {
+            if entry.first_step <= step && step <= entry.last_step {
+                inputs.entry(entry.owner).or_default().push(entry.action);
+            }
+        }
+        inputs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let script = Script::parse(
+            "# Both blobs go forward\n\
+             0..9 0 Forward\n\
+             \n\
+             5 1 TurnLeft 0.5 # and one turns\n",
+        )
+        .unwrap();
+
+        assert_eq!(script.last_step(), Some(9));
+        assert_eq!(
+            script.inputs(5),
+            vec![
+                (0, vec![Action::new(InputEvent::Forward, 1.0)]),
+                (1, vec![Action::new(InputEvent::TurnLeft, 0.5)]),
+            ]
+            .into_iter()
+            .collect()
+        );
+        assert_eq!(script.inputs(6).len(), 1);
+        assert!(script.inputs(10).is_empty());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let line = |source: &str| Script::parse(source).unwrap_err().line;
+
+        assert_eq!(line("0 0 Forward\n1 0 Jump"), 2);
+        assert_eq!(line("\n\n5..1 0 Forward"), 3);
+        assert_eq!(line("x 0 Forward"), 1);
+        assert_eq!(line("0 -1 Forward"), 1);
+        assert_eq!(line("0 0 Forward fast"), 1);
+        assert_eq!(line("0 0 Forward\n0..50 0 Forward nan"), 2);
+        assert_eq!(line("0..50 0 Forward inf"), 1);
+        assert_eq!(line("0..50 0 Forward -inf"), 1);
+        assert_eq!(line("0 0"), 1);
+    }
+}
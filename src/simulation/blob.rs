@@ -1,10 +1,42 @@
+use super::tuning::Tuning;
 use crate::math::circle::Circle;
 use crate::math::point::PointF32;
 use crate::math::rect::Rect;
+use serde::{Deserialize, Serialize};
 
 pub type BlobId = u32;
+// The player (or script) that controls a blob. A blob that splits keeps
+// its owner, so one player can control several blobs.
+pub type OwnerId = u32;
 
-#[derive(Debug)]
+// Mass of a blob unless told otherwise.
+pub const DEFAULT_MASS: f32 = 13.0;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BlobDesc {
+    pub pos: PointF32,
+    pub radius: f32,
+    pub mass: f32,
+    pub rotation: f32,
+    pub health: f32,
+    // None gives the blob an owner of its own, with the same id as the blob.
+    pub owner: Option<OwnerId>,
+}
+
+impl BlobDesc {
+    pub fn new(x: f32, y: f32, radius: f32) -> Self {
+        BlobDesc {
+            pos: PointF32::new(x, y),
+            radius,
+            mass: DEFAULT_MASS,
+            rotation: 0.0,
+            health: 100.0,
+            owner: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Blob {
     pub id: BlobId,
     pub circle: Circle,
@@ -15,32 +47,65 @@ pub struct Blob {
     pub angular_velocity: f32,
     pub angular_force: f32,
     pub collision_aabb: Rect,
+    // State at the start of the last step, used to interpolate when rendering.
+    pub previous_pos: PointF32,
+    pub previous_rotation: f32,
+    // The blob dies when it reaches zero health.
+    pub health: f32,
+    pub max_health: f32,
+    pub owner: OwnerId,
+    // Seconds left until the blob can merge with the other blobs of its
+    // owner again.
+    pub merge_timer: f32,
+    // Seconds left until the blob can fire again.
+    pub fire_cooldown: f32,
 }
 
 impl Blob {
     pub fn new(x: f32, y: f32, radius: f32, id: BlobId) -> Self {
+        Blob::new_from_desc(&BlobDesc::new(x, y, radius), id)
+    }
+
+    pub fn new_from_desc(desc: &BlobDesc, id: BlobId) -> Self {
         Blob {
             id,
-            circle: Circle::new(x, y, radius),
-            mass: 13.0,
+            circle: Circle::new_from_pos(desc.pos, desc.radius),
+            mass: desc.mass,
             velocity: PointF32::new(0.0, 0.0),
             force: PointF32::new(0.0, 0.0),
-            rotation: 0.0,
+            rotation: desc.rotation,
             angular_velocity: 0.0,
             angular_force: 0.0,
             collision_aabb: Rect::new_empty(),
+            previous_pos: desc.pos,
+            previous_rotation: desc.rotation,
+            health: desc.health,
+            max_health: desc.health,
+            owner: desc.owner.unwrap_or(id),
+            merge_timer: 0.0,
+            fire_cooldown: 0.0,
         }
     }
 
+    // Adds the area of a circle with 'radius' and 'mass', e.g. of something
+    // the blob has eaten.
+    pub fn grow(&mut self, radius: f32, mass: f32) {
+        self.circle.radius = (self.circle.radius * self.circle.radius + radius * radius).sqrt();
+        self.mass += mass;
+    }
+
     fn clamp(value: f32, min: f32, max: f32) -> f32 {
         value.min(max).max(min)
     }
 
-    pub fn update(&mut self, dt: f32) {
+    pub fn update(&mut self, dt: f32, tuning: &Tuning) {
+        self.previous_pos = self.circle.pos;
+        self.previous_rotation = self.rotation;
+
         if self.velocity.magnitude().abs() > f32::EPSILON {
             self.force -= self.velocity / self.velocity.magnitude()
                 * self.velocity.dot(&self.velocity)
-                * 0.2
+                * tuning.drag
                 * self.mass
                 * dt;
         }
@@ -49,7 +114,7 @@ impl Blob {
             self.angular_force -= self.angular_velocity.signum()
                 * self.angular_velocity
                 * self.angular_velocity
-                * 1000.0
+                * tuning.angular_drag
                 * dt; // Or something
         }
 
@@ -59,15 +124,50 @@ impl Blob {
         self.velocity += acceleration * dt;
         self.angular_velocity += angular_acceleration * dt;
 
-        // We probably want to update the collision AABB before moving the actual object.
-        self.collision_aabb = Rect::new(self.circle.pos, self.circle.pos + self.velocity * dt);
+        // The collision AABB covers the whole sweep of this step, the actual
+        // move is done in 'advance' once collisions have been resolved.
+        self.collision_aabb =
+            Rect::new_valid(self.circle.pos, self.circle.pos + self.velocity * dt);
         self.collision_aabb
             .grow(self.circle.radius, self.circle.radius);
 
+        self.force = PointF32::new(0.0, 0.0);
+        self.angular_force = 0.0;
+    }
+
+    pub fn advance(&mut self, dt: f32) {
         self.circle.pos += self.velocity * dt;
         self.rotation += self.angular_velocity * dt;
+    }
 
-        self.force = PointF32::new(0.0, 0.0);
-        self.angular_force = 0.0;
+    pub fn interpolated_pos(&self, alpha: f32) -> PointF32 {
+        self.previous_pos + (self.circle.pos - self.previous_pos) * alpha
+    }
+
+    pub fn interpolated_rotation(&self, alpha: f32) -> f32 {
+        self.previous_rotation + (self.rotation - self.previous_rotation) * alpha
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interpolation() {
+        let mut blob = Blob::new(10.0, 20.0, 5.0, 0);
+        blob.velocity = PointF32::new(10.0, -20.0);
+        blob.angular_velocity = 2.0;
+        blob.update(0.5, &Tuning::default());
+        blob.advance(0.5);
+
+        assert_eq!(blob.interpolated_pos(0.0), PointF32::new(10.0, 20.0));
+        assert_eq!(blob.interpolated_pos(1.0), blob.circle.pos);
+        assert_eq!(blob.interpolated_rotation(0.0), 0.0);
+        assert_eq!(blob.interpolated_rotation(1.0), blob.rotation);
+
+        let halfway = blob.interpolated_pos(0.5);
+        assert!((halfway.x - (10.0 + blob.circle.pos.x) / 2.0).abs() < 0.0001);
+        assert!((halfway.y - (20.0 + blob.circle.pos.y) / 2.0).abs() < 0.0001);
     }
 }
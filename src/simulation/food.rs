@@ -0,0 +1,44 @@
+use crate::logic::loose_grid::GridObject;
+use crate::math::point::PointF32;
+use crate::math::random::xorshift;
+use crate::math::rect::Rect;
+use serde::{Deserialize, Serialize};
+
+// Pellets are all the same size, a blob that eats one gets its area and
+// mass.
+pub const FOOD_RADIUS: f32 = 2.0;
+pub const FOOD_MASS: f32 = 0.5;
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Food {
+    pub pos: PointF32,
+    // Set while eating, the pellet is taken out of the grid right after.
+    #[serde(skip)]
+    pub(super) eaten: bool,
+}
+
+impl Food {
+    pub fn new(pos: PointF32) -> Self {
+        Food { pos, eaten: false }
+    }
+}
+
+impl GridObject for Food {
+    fn pos(&self) -> PointF32 {
+        self.pos
+    }
+
+    fn radius(&self) -> f32 {
+        FOOD_RADIUS
+    }
+}
+
+// A random place for a pellet, completely inside 'rect'. 'state' is part of
+// the simulation state so the pellets come back at the same places in every
+// run.
+pub(super) fn random_pos(rect: &Rect, state: &mut u64) -> PointF32 {
+    let size = rect.size();
+    let x = xorshift(state) * (size.x - 2.0 * FOOD_RADIUS);
+    let y = xorshift(state) * (size.y - 2.0 * FOOD_RADIUS);
+    rect.bottom_left + PointF32::new(x + FOOD_RADIUS, y + FOOD_RADIUS)
+}
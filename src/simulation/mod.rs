@@ -1,5 +1,13 @@
 pub mod blob;
+pub mod food;
+pub mod projectile;
 pub mod simulation;
+pub mod snapshot;
+pub mod tuning;
 
+pub use simulation::Action;
+pub use simulation::Event;
 pub use simulation::InputEvent;
 pub use simulation::Simulation;
+pub use snapshot::Snapshot;
+pub use tuning::Tuning;
@@ -0,0 +1,53 @@
+use super::blob::{BlobId, OwnerId};
+use crate::math::circle::Circle;
+use crate::math::point::PointF32;
+use crate::math::rect::Rect;
+use serde::{Deserialize, Serialize};
+
+pub const PROJECTILE_RADIUS: f32 = 2.0;
+// On top of the velocity of the blob that fired it.
+pub const PROJECTILE_SPEED: f32 = 800.0;
+// Seconds before a projectile that hit nothing is gone.
+pub const PROJECTILE_LIFETIME: f32 = 1.5;
+pub const PROJECTILE_DAMAGE: f32 = 10.0;
+// Only used to push the blob that is hit.
+pub const PROJECTILE_MASS: f32 = 0.5;
+// Seconds between two shots of a blob.
+pub const FIRE_COOLDOWN: f32 = 0.25;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Projectile {
+    // The blob that fired it, blobs of the same owner are not hit.
+    pub shooter: BlobId,
+    pub owner: OwnerId,
+    pub circle: Circle,
+    pub velocity: PointF32,
+    pub time_left: f32,
+    // Position at the start of the last step, used to interpolate when
+    // rendering.
+    pub previous_pos: PointF32,
+}
+
+impl Projectile {
+    pub fn new(shooter: BlobId, owner: OwnerId, pos: PointF32, velocity: PointF32) -> Self {
+        Projectile {
+            shooter,
+            owner,
+            circle: Circle::new_from_pos(pos, PROJECTILE_RADIUS),
+            velocity,
+            time_left: PROJECTILE_LIFETIME,
+            previous_pos: pos,
+        }
+    }
+
+    // Covers the whole move of the next step.
+    pub fn sweep_aabb(&self, dt: f32) -> Rect {
+        let mut aabb = Rect::new_valid(self.circle.pos, self.circle.pos + self.velocity * dt);
+        aabb.grow(self.circle.radius, self.circle.radius);
+        aabb
+    }
+
+    pub fn interpolated_pos(&self, alpha: f32) -> PointF32 {
+        self.previous_pos + (self.circle.pos - self.previous_pos) * alpha
+    }
+}
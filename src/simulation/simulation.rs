@@ -1,31 +1,132 @@
-use super::blob::{Blob, BlobId};
+use super::blob::{Blob, BlobDesc, BlobId, OwnerId, DEFAULT_MASS};
+use super::food::{self, Food, FOOD_MASS, FOOD_RADIUS};
+use super::projectile::{
+    Projectile, FIRE_COOLDOWN, PROJECTILE_DAMAGE, PROJECTILE_MASS, PROJECTILE_RADIUS,
+    PROJECTILE_SPEED,
+};
 
+use super::snapshot::Snapshot;
+use super::tuning::Tuning;
+use crate::level::Level;
 use crate::logic::aabb_grid::AabbGrid;
 use crate::logic::aabb_grid::AabbObject;
+use crate::logic::loose_grid::{GridObject, LooseGrid};
+use crate::math::circle::Circle;
+use crate::math::collision;
 use crate::math::point::PointF32;
 use crate::math::rect::Rect;
+use crate::math::trig;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::vec::Vec;
 
-#[derive(Debug, PartialEq, Eq, Copy, Clone, Ord, PartialOrd, Hash)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Ord, PartialOrd, Hash, Serialize, Deserialize)]
 pub enum InputEvent {
     Forward,
     Backward,
     TurnLeft,
     TurnRight,
+    // Splits every blob of the owner that is large enough in two.
+    Split,
+    // Every blob of the owner that isn't cooling down fires a projectile.
+    Fire,
 }
 
+// An input event with how strongly it is applied, 1.0 is the normal
+// strength, e.g. analogue sticks or throttle chords can give other values.
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub struct Action {
+    pub event: InputEvent,
+    pub value: f32,
+}
+
+impl Action {
+    pub fn new(event: InputEvent, value: f32) -> Self {
+        Action { event, value }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Event {
+    // Blob ids, contact point and the size of the impulse.
+    Collision((BlobId, BlobId, PointF32, f32)),
+    WallHit((BlobId, PointF32)),
+    Spawn((BlobId, PointF32)),
+    Remove(BlobId),
     Kill((PointF32, f32)),
+    // The first blob ate the second one.
+    Absorb((BlobId, BlobId)),
+    // Blob id and how many pellets it ate.
+    Eat((BlobId, u32)),
+    // The blob that split and the new half.
+    Split((BlobId, BlobId)),
+    // Two blobs of the same owner became one, the first one is kept.
+    Merge((BlobId, BlobId)),
+    // The blob that fired and where the projectile starts.
+    Shot((BlobId, PointF32)),
+    // A projectile hit a blob: blob id, where it hit and the damage dealt.
+    Damage((BlobId, PointF32, f32)),
 }
 
+// A blob can eat another one that has a radius this much smaller, the two
+// bounce off each other otherwise.
+const ABSORB_RATIO: f32 = 1.25;
+// How much of the smaller blob has to be inside the larger one before it is
+// eaten, as a part of its radius.
+const ABSORB_DEPTH: f32 = 0.5;
+// Smallest blob that can split, the halves have a radius of 1/sqrt(2) of it.
+const SPLIT_MIN_RADIUS: f32 = 15.0;
+// Most blobs one owner can have.
+const MAX_FRAGMENTS: usize = 16;
+// Speed the new half is launched with, on top of the blob's own.
+const SPLIT_SPEED: f32 = 300.0;
+// Seconds after a split before the halves can merge again, after that they
+// are pulled towards each other.
+const MERGE_TIME: f32 = 8.0;
+const MERGE_PULL: f32 = 30000.0;
+// Eaten pellets come back a few at a time.
+const FOOD_RESPAWN_PER_STEP: usize = 2;
+// Attempts to place a pellet outside the obstacles before giving up and
+// leaving it where it is.
+const FOOD_PLACEMENT_TRIES: usize = 8;
+// The arena of a game started without a level file.
+const DEFAULT_LEVEL: &str = include_str!("../../levels/default.ron");
+
 pub struct Simulation {
     rect: Rect,
+    wall_restitution: f32,
+    tuning: Tuning,
+    // Static rects inside the arena, blobs bounce off them like off the
+    // walls.
+    obstacles: Vec<Rect>,
+    // Collisions with a larger impulse than 'damage_threshold' deal damage,
+    // 'damage_scale' per unit of impulse above it.
+    damage_threshold: f32,
+    damage_scale: f32,
     blobs: Vec<Blob>,
+    // Index into 'blobs' for each live blob.
+    blob_indices: HashMap<BlobId, usize>,
+    next_blob_id: BlobId,
+    // Persistent spatial index of the blobs, used to look up visible blobs.
+    blob_grid: LooseGrid<GridBlob>,
+    food: LooseGrid<Food>,
+    food_count: usize,
+    // Eaten pellets are respawned until there are 'max_food' of them again.
+    max_food: usize,
+    food_seed: u64,
+    food_random: u64,
+    projectiles: Vec<Projectile>,
+    // Events that happened between two steps, returned by the next 'simulate'.
+    events: Vec<Event>,
+    // Number of steps simulated so far.
+    step: u64,
+    // Use platform independent math so results are bit-exact everywhere.
+    deterministic: bool,
 }
 
 #[derive(Debug)]
 pub struct SweepBlob<'a> {
+    index: usize,
     blob: &'a Blob,
 }
 
@@ -35,78 +136,1728 @@ impl<'a> AabbObject for SweepBlob<'a> {
     }
 }
 
+#[derive(Debug)]
+struct GridBlob {
+    id: BlobId,
+    pos: PointF32,
+    radius: f32,
+}
+
+impl GridBlob {
+    fn new(blob: &Blob) -> Self {
+        // The radius also covers the last move, so a blob drawn
+        // interpolated between two steps is still found.
+        GridBlob {
+            id: blob.id,
+            pos: blob.circle.pos,
+            radius: blob.circle.radius + (blob.circle.pos - blob.previous_pos).magnitude(),
+        }
+    }
+}
+
+impl GridObject for GridBlob {
+    fn pos(&self) -> PointF32 {
+        self.pos
+    }
+
+    fn radius(&self) -> f32 {
+        self.radius
+    }
+}
+
 impl Simulation {
     pub fn new() -> Self {
-        let rect = Rect::new(
-            PointF32::new(-1000.0, -1000.0),
-            PointF32::new(1000.0, 1000.0),
-        );
-        let blobs = vec![
-            Blob::new(100.0, 100.0, 10.0, 0),
-            Blob::new(140.0, 100.0, 10.0, 1),
-        ];
-        Simulation { rect, blobs }
+        Level::parse(DEFAULT_LEVEL)
+            .expect("levels/default.ron is invalid")
+            .simulation()
+    }
+
+    pub fn new_empty(rect: Rect) -> Self {
+        Simulation {
+            rect,
+            wall_restitution: 0.5,
+            tuning: Tuning::default(),
+            obstacles: Vec::new(),
+            damage_threshold: 500.0,
+            damage_scale: 0.05,
+            blobs: Vec::new(),
+            blob_indices: HashMap::new(),
+            next_blob_id: 0,
+            blob_grid: LooseGrid::new(rect, 20, 20),
+            food: LooseGrid::new(rect, 20, 20),
+            food_count: 0,
+            max_food: 0,
+            food_seed: 0,
+            food_random: 1,
+            projectiles: Vec::new(),
+            events: Vec::new(),
+            step: 0,
+            deterministic: false,
+        }
+    }
+
+    pub fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    pub fn set_deterministic(&mut self, deterministic: bool) {
+        self.deterministic = deterministic;
+    }
+
+    pub fn is_deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    pub fn step(&self) -> u64 {
+        self.step
+    }
+
+    fn direction(&self, rotation: f32) -> PointF32 {
+        if self.deterministic {
+            PointF32::new(trig::cos(rotation), trig::sin(rotation))
+        } else {
+            PointF32::new(rotation.cos(), rotation.sin())
+        }
+    }
+
+    // FNV-1a over the state of all blobs in id order, two simulations that
+    // have the same hash after a step are (almost certainly) in the same
+    // state.
+    pub fn state_hash(&self) -> u64 {
+        let mut hash = 0xcbf2_9ce4_8422_2325u64;
+        let mut write = |value: u32| {
+            for byte in value.to_le_bytes().iter() {
+                hash ^= *byte as u64;
+                hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+            }
+        };
+
+        write(self.step as u32);
+        write((self.step >> 32) as u32);
+
+        let mut blobs: Vec<&Blob> = self.blobs.iter().collect();
+        blobs.sort_by_key(|blob| blob.id);
+        for blob in blobs {
+            write(blob.id);
+            write(blob.owner);
+            for value in &[
+                blob.circle.pos.x,
+                blob.circle.pos.y,
+                blob.circle.radius,
+                blob.mass,
+                blob.velocity.x,
+                blob.velocity.y,
+                blob.rotation,
+                blob.angular_velocity,
+                blob.health,
+                blob.merge_timer,
+                blob.fire_cooldown,
+            ] {
+                write(value.to_bits());
+            }
+        }
+
+        for projectile in &self.projectiles {
+            write(projectile.shooter);
+            for value in &[
+                projectile.circle.pos.x,
+                projectile.circle.pos.y,
+                projectile.velocity.x,
+                projectile.velocity.y,
+                projectile.time_left,
+            ] {
+                write(value.to_bits());
+            }
+        }
+
+        // The pellets only change by being eaten and respawned, the count and
+        // the random state follow both.
+        write(self.food_count as u32);
+        write(self.food_random as u32);
+        write((self.food_random >> 32) as u32);
+
+        hash
+    }
+
+    pub fn wall_restitution(&self) -> f32 {
+        self.wall_restitution
+    }
+
+    pub fn set_wall_restitution(&mut self, restitution: f32) {
+        self.wall_restitution = restitution;
+    }
+
+    pub fn tuning(&self) -> Tuning {
+        self.tuning
+    }
+
+    // Takes effect from the next step on.
+    pub fn set_tuning(&mut self, tuning: Tuning) {
+        self.tuning = tuning;
+    }
+
+    // Pellets are placed outside the obstacles, so they should be added
+    // before 'set_food'.
+    pub fn add_obstacle(&mut self, rect: Rect) {
+        self.obstacles.push(rect);
+    }
+
+    pub fn clear_obstacles(&mut self) {
+        self.obstacles.clear();
+    }
+
+    pub fn obstacles(&self) -> &[Rect] {
+        &self.obstacles
+    }
+
+    // Impulse threshold and damage per unit of impulse above it.
+    pub fn damage(&self) -> (f32, f32) {
+        (self.damage_threshold, self.damage_scale)
+    }
+
+    pub fn set_damage(&mut self, threshold: f32, scale: f32) {
+        self.damage_threshold = threshold;
+        self.damage_scale = scale;
+    }
+
+    // Replaces the food with 'max_food' pellets placed from 'seed', eaten
+    // ones are respawned in later steps.
+    pub fn set_food(&mut self, max_food: usize, seed: u64) {
+        self.food = LooseGrid::new(self.rect, 20, 20);
+        self.food_count = 0;
+        self.max_food = max_food;
+        self.food_seed = seed;
+        // Xorshift doesn't work with a zero state.
+        self.food_random = seed | 1;
+        self.spawn_food(max_food);
+    }
+
+    // The number of pellets and the seed given to 'set_food'.
+    pub fn food_settings(&self) -> (usize, u64) {
+        (self.max_food, self.food_seed)
+    }
+
+    pub fn food_count(&self) -> usize {
+        self.food_count
+    }
+
+    fn spawn_food(&mut self, count: usize) {
+        let count = count.min(self.max_food.saturating_sub(self.food_count));
+        for _ in 0..count {
+            let mut pos = food::random_pos(&self.rect, &mut self.food_random);
+            for _ in 1..FOOD_PLACEMENT_TRIES {
+                if !self.obstacles.iter().any(|obstacle| {
+                    collision::collides_rect(&Circle::new_from_pos(pos, FOOD_RADIUS), obstacle)
+                        .is_some()
+                }) {
+                    break;
+                }
+                pos = food::random_pos(&self.rect, &mut self.food_random);
+            }
+            self.food.add(Food::new(pos));
+        }
+        self.food_count += count;
+    }
+
+    fn allocate_blob_id(&mut self) -> BlobId {
+        // Ids are handed out in increasing order, so they are only reused
+        // once the counter wraps around, and then never while still alive.
+        loop {
+            let blob_id = self.next_blob_id;
+            self.next_blob_id = self.next_blob_id.wrapping_add(1);
+            if !self.blob_indices.contains_key(&blob_id) {
+                return blob_id;
+            }
+        }
+    }
+
+    fn add_blob(&mut self, blob: Blob) {
+        self.events.push(Event::Spawn((blob.id, blob.circle.pos)));
+        self.blob_indices.insert(blob.id, self.blobs.len());
+        self.blob_grid.add(GridBlob::new(&blob));
+        self.blobs.push(blob);
+    }
+
+    pub fn spawn_blob(&mut self, desc: &BlobDesc) -> BlobId {
+        let blob_id = self.allocate_blob_id();
+        self.add_blob(Blob::new_from_desc(desc, blob_id));
+        blob_id
+    }
+
+    // Adds 'blob' under its own id, or replaces the blob with that id. Used
+    // to mirror a simulation that runs somewhere else, e.g. on a server.
+    pub fn set_blob(&mut self, blob: Blob) {
+        self.blob_grid.remove_if(|entry| entry.id == blob.id);
+        self.blob_grid.add(GridBlob::new(&blob));
+        match self.blob_indices.get(&blob.id) {
+            Some(index) => self.blobs[*index] = blob,
+            None => {
+                self.blob_indices.insert(blob.id, self.blobs.len());
+                self.blobs.push(blob);
+            }
+        }
+    }
+
+    pub fn despawn(&mut self, blob_id: BlobId) -> Option<Blob> {
+        let index = self.blob_indices.remove(&blob_id)?;
+        let blob = self.blobs.swap_remove(index);
+        if let Some(moved) = self.blobs.get(index) {
+            self.blob_indices.insert(moved.id, index);
+        }
+        self.blob_grid.remove_if(|entry| entry.id == blob_id);
+        self.events.push(Event::Remove(blob_id));
+        Some(blob)
+    }
+
+    // Events queued since the last step, e.g. by 'despawn'. A simulation
+    // that only mirrors another one never steps, so it has to drop them.
+    pub fn take_events(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.events)
+    }
+
+    // Everything needed to continue the simulation later from this point,
+    // the spatial indices and id lookup are rebuilt when restoring.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            rect: self.rect,
+            wall_restitution: self.wall_restitution,
+            tuning: self.tuning,
+            obstacles: self.obstacles.clone(),
+            damage_threshold: self.damage_threshold,
+            damage_scale: self.damage_scale,
+            blobs: self.blobs.clone(),
+            food: self.food(self.rect),
+            max_food: self.max_food,
+            food_seed: self.food_seed,
+            food_random: self.food_random,
+            projectiles: self.projectiles.clone(),
+            next_blob_id: self.next_blob_id,
+            events: self.events.clone(),
+            step: self.step,
+            deterministic: self.deterministic,
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.rect = snapshot.rect;
+        self.wall_restitution = snapshot.wall_restitution;
+        self.tuning = snapshot.tuning;
+        self.obstacles = snapshot.obstacles.clone();
+        self.damage_threshold = snapshot.damage_threshold;
+        self.damage_scale = snapshot.damage_scale;
+        self.blobs = snapshot.blobs.clone();
+        self.blob_indices = self
+            .blobs
+            .iter()
+            .enumerate()
+            .map(|(index, blob)| (blob.id, index))
+            .collect();
+        self.next_blob_id = snapshot.next_blob_id;
+        self.blob_grid = LooseGrid::new(self.rect, 20, 20);
+        for blob in &self.blobs {
+            self.blob_grid.add(GridBlob::new(blob));
+        }
+        self.food = LooseGrid::new(self.rect, 20, 20);
+        for food in &snapshot.food {
+            self.food.add(*food);
+        }
+        self.food_count = snapshot.food.len();
+        self.max_food = snapshot.max_food;
+        self.food_seed = snapshot.food_seed;
+        self.food_random = snapshot.food_random;
+        self.projectiles = snapshot.projectiles.clone();
+        self.events = snapshot.events.clone();
+        self.step = snapshot.step;
+        self.deterministic = snapshot.deterministic;
+    }
+
+    pub fn blobs(&self) -> &[Blob] {
+        &self.blobs
+    }
+
+    pub fn find_blob(&self, blob_id: BlobId) -> Option<&Blob> {
+        let index = *self.blob_indices.get(&blob_id)?;
+        Some(&self.blobs[index])
     }
 
     pub fn find_blob_mut(&mut self, blob_id: BlobId) -> Option<&mut Blob> {
-        // TODO, temporary way. In the end we might to have a map
-        // from ID to vector pos, or have the blobs stored in order (so
-        // we can do a binary search).
-        self.blobs.iter_mut().find(|blob| blob.id == blob_id)
-    }
-
-    pub fn simulate(&mut self, inputs: &HashMap<BlobId, Vec<InputEvent>>, dt: f32) -> Vec<Event> {
-        // Some temporary values, might be more complicated (e.g. depend on
-        // the current velocity or how long one has throttled).
-        let angle_thrust = 4000f32;
-        let thrust = 100000f32;
-
-        for (blob_id, events) in inputs {
-            if let Some(blob) = &mut self.find_blob_mut(*blob_id) {
-                for event in events {
-                    match event {
+        let index = *self.blob_indices.get(&blob_id)?;
+        Some(&mut self.blobs[index])
+    }
+
+    // The inputs are given per owner.
+    pub fn simulate(&mut self, inputs: &HashMap<OwnerId, Vec<Action>>, dt: f32) -> Vec<Event> {
+        // Might be more complicated some day (e.g. depend on the current
+        // velocity or how long one has throttled).
+        let angle_thrust = self.tuning.angle_thrust;
+        let thrust = self.tuning.thrust;
+        let restitution = 0.8f32;
+
+        // Inputs are applied in a fixed order, the iteration order of a
+        // HashMap differs between runs and floating point addition is not
+        // associative.
+        let mut ordered_inputs: Vec<(OwnerId, Vec<Action>)> = inputs
+            .iter()
+            .map(|(owner, actions)| (*owner, actions.clone()))
+            .collect();
+        ordered_inputs.sort_by_key(|(owner, _)| *owner);
+
+        for (owner, mut actions) in ordered_inputs {
+            actions.sort_by(|a, b| {
+                a.event
+                    .cmp(&b.event)
+                    .then(a.value.to_bits().cmp(&b.value.to_bits()))
+            });
+            // Every blob of the owner gets the same inputs.
+            for blob_id in self.owned_blobs(owner) {
+                let direction = self.direction(self.find_blob(blob_id).unwrap().rotation);
+                let blob = self.find_blob_mut(blob_id).unwrap();
+                // Blobs that have eaten a lot are slower, with the drag growing
+                // with mass too the top speed drops faster than the thrust.
+                let thrust = thrust * (DEFAULT_MASS / blob.mass).sqrt();
+                let mut split = false;
+                let mut fire = false;
+                for action in &actions {
+                    let value = action.value;
+                    match action.event {
                         InputEvent::Forward => {
-                            blob.force += PointF32::new(blob.rotation.cos(), blob.rotation.sin())
-                                * thrust
-                                * dt;
+                            blob.force += direction * thrust * value * dt;
                         }
                         InputEvent::Backward => {
-                            blob.force -= PointF32::new(blob.rotation.cos(), blob.rotation.sin())
-                                * thrust
-                                * dt
-                                * 0.5;
+                            blob.force -= direction * thrust * value * dt * 0.5;
                         }
-                        InputEvent::TurnLeft => blob.angular_force -= angle_thrust * dt,
-                        InputEvent::TurnRight => blob.angular_force += angle_thrust * dt,
+                        InputEvent::TurnLeft => blob.angular_force -= angle_thrust * value * dt,
+                        InputEvent::TurnRight => blob.angular_force += angle_thrust * value * dt,
+                        InputEvent::Split => split = true,
+                        InputEvent::Fire => fire = true,
                     }
                 }
+                if fire {
+                    self.fire(blob_id, direction);
+                }
+                if split {
+                    self.split_blob(blob_id, direction);
+                }
             }
         }
 
+        self.pull_fragments(dt);
         for blob in &mut self.blobs {
-            blob.update(dt);
+            blob.merge_timer = (blob.merge_timer - dt).max(0.0);
+            blob.fire_cooldown = (blob.fire_cooldown - dt).max(0.0);
+            blob.update(dt, &self.tuning);
         }
 
-        let grid = AabbGrid::new_with_objects(
-            self.rect,
-            20,
-            20,
-            self.blobs.iter().map(|blob| SweepBlob { blob: &blob }),
-        );
+        let mut contacts = Vec::<(usize, usize, f32)>::new();
+        // Projectile index, index of the blob it hits or None for an
+        // obstacle, and time of the first hit of each projectile that hits
+        // something.
+        let mut hits = Vec::<(usize, Option<usize>, f32)>::new();
+        {
+            let grid = AabbGrid::new_with_objects(
+                self.rect,
+                20,
+                20,
+                self.blobs
+                    .iter()
+                    .enumerate()
+                    .map(|(index, blob)| SweepBlob { index, blob }),
+            );
+
+            for (index, blob) in self.blobs.iter().enumerate() {
+                grid.for_objects(&blob.collision_aabb, |other| {
+                    if !std::ptr::eq(blob, other.blob) && blob.id < other.blob.id {
+                        if let Some(t) = collision::collides_before(
+                            (&blob.circle, &blob.velocity),
+                            (&other.blob.circle, &other.blob.velocity),
+                            dt,
+                        ) {
+                            contacts.push((index, other.index, t));
+                        }
+                    }
+                });
+            }
+
+            for (index, projectile) in self.projectiles.iter().enumerate() {
+                let mut first_hit: Option<(usize, f32)> = None;
+                grid.for_objects(&projectile.sweep_aabb(dt), |other| {
+                    if other.blob.owner == projectile.owner {
+                        return;
+                    }
+                    if let Some(t) = collision::collides_before(
+                        (&projectile.circle, &projectile.velocity),
+                        (&other.blob.circle, &other.blob.velocity),
+                        dt,
+                    ) {
+                        if first_hit.is_none_or(|(_, first_t)| t < first_t) {
+                            first_hit = Some((other.index, t));
+                        }
+                    }
+                });
+                // A blob behind an obstacle is safe.
+                let blocked_at = self
+                    .obstacles
+                    .iter()
+                    .filter_map(|obstacle| {
+                        collision::collides_rect_before(
+                            &projectile.circle,
+                            &projectile.velocity,
+                            obstacle,
+                            dt,
+                        )
+                    })
+                    .reduce(f32::min);
+                match (first_hit, blocked_at) {
+                    (Some((blob_index, t)), Some(blocked_t)) if t < blocked_t => {
+                        hits.push((index, Some(blob_index), t))
+                    }
+                    (Some((blob_index, t)), None) => hits.push((index, Some(blob_index), t)),
+                    (_, Some(blocked_t)) => hits.push((index, None, blocked_t)),
+                    (None, None) => (),
+                }
+            }
+        }
+
+        let mut events = std::mem::take(&mut self.events);
+
+        // Resolve the earliest contacts first, a blob hit twice in the same
+        // step will then bounce off the second one with its new velocity.
+        contacts.sort_by(|a, b| a.2.total_cmp(&b.2));
+        for (i, j, t) in contacts {
+            let (first, second) = if i < j {
+                let (left, right) = self.blobs.split_at_mut(j);
+                (&mut left[i], &mut right[0])
+            } else {
+                let (left, right) = self.blobs.split_at_mut(i);
+                (&mut right[0], &mut left[j])
+            };
+            // A blob that can eat or merge with the other one moves over it
+            // instead.
+            if Simulation::can_absorb(first, second)
+                || Simulation::can_absorb(second, first)
+                || Simulation::can_merge(first, second)
+            {
+                continue;
+            }
+            if let Some((contact, impulse)) =
+                Simulation::resolve_contact(first, second, t, restitution)
+            {
+                // Blobs of the same owner don't hurt each other.
+                if first.owner != second.owner {
+                    Simulation::deal_damage(
+                        first,
+                        second,
+                        impulse,
+                        self.damage_threshold,
+                        self.damage_scale,
+                    );
+                }
+                events.push(Event::Collision((first.id, second.id, contact, impulse)));
+            }
+        }
+
+        for blob in &mut self.blobs {
+            if let Some(contact) =
+                Simulation::bounce_off_walls(&self.rect, blob, dt, self.wall_restitution)
+            {
+                events.push(Event::WallHit((blob.id, contact)));
+            }
+            blob.advance(dt);
+            for obstacle in &self.obstacles {
+                if let Some(contact) =
+                    Simulation::bounce_off_obstacle(obstacle, blob, self.wall_restitution)
+                {
+                    events.push(Event::WallHit((blob.id, contact)));
+                }
+            }
+        }
+        self.move_projectiles(&hits, dt, &mut events);
+
+        let dead: Vec<BlobId> = self
+            .blobs
+            .iter()
+            .filter(|blob| blob.health <= 0.0)
+            .map(|blob| blob.id)
+            .collect();
+        for blob_id in dead {
+            if let Some(blob) = self.despawn(blob_id) {
+                events.push(Event::Kill((blob.circle.pos, blob.circle.radius)));
+            }
+        }
+
+        self.sync_grid();
+        self.eat_food(&mut events);
+        self.spawn_food(FOOD_RESPAWN_PER_STEP);
+        self.absorb_blobs(&mut events);
+        // The removals are reported in this step, together with the kills
+        // and absorptions.
+        events.append(&mut self.events);
+
+        self.step += 1;
+        events
+    }
+
+    fn fire(&mut self, blob_id: BlobId, direction: PointF32) {
+        let blob = self.find_blob_mut(blob_id).unwrap();
+        if blob.fire_cooldown > 0.0 {
+            return;
+        }
+        blob.fire_cooldown = FIRE_COOLDOWN;
+        let pos = blob.circle.pos + direction * (blob.circle.radius + PROJECTILE_RADIUS);
+        let velocity = blob.velocity + direction * PROJECTILE_SPEED;
+        let projectile = Projectile::new(blob_id, blob.owner, pos, velocity);
+        self.projectiles.push(projectile);
+        self.events.push(Event::Shot((blob_id, pos)));
+    }
+
+    // Projectiles that hit a blob damage and push it and are gone, the
+    // others move on until their time is up, they hit an obstacle or they
+    // leave the arena.
+    fn move_projectiles(
+        &mut self,
+        hits: &[(usize, Option<usize>, f32)],
+        dt: f32,
+        events: &mut Vec<Event>,
+    ) {
+        let mut hit = vec![false; self.projectiles.len()];
+        for (projectile_index, blob_index, t) in hits {
+            hit[*projectile_index] = true;
+            let blob_index = match blob_index {
+                Some(blob_index) => blob_index,
+                None => continue,
+            };
+            let projectile = &self.projectiles[*projectile_index];
+            let blob = &mut self.blobs[*blob_index];
+            let contact = projectile.circle.pos + projectile.velocity * *t;
+            blob.health -= PROJECTILE_DAMAGE;
+            blob.velocity += projectile.velocity * (PROJECTILE_MASS / blob.mass);
+            events.push(Event::Damage((blob.id, contact, PROJECTILE_DAMAGE)));
+        }
+
+        let rect = self.rect;
+        let mut index = 0;
+        self.projectiles.retain_mut(|projectile| {
+            index += 1;
+            if hit[index - 1] {
+                return false;
+            }
+            projectile.previous_pos = projectile.circle.pos;
+            projectile.circle.pos += projectile.velocity * dt;
+            projectile.time_left -= dt;
+            let pos = projectile.circle.pos;
+            projectile.time_left > 0.0
+                && pos.x >= rect.bottom_left.x
+                && pos.x <= rect.top_right.x
+                && pos.y >= rect.bottom_left.y
+                && pos.y <= rect.top_right.y
+        });
+    }
 
-        // TODO Initial collision detection can be done something like this
+    pub fn projectiles(&self) -> &[Projectile] {
+        &self.projectiles
+    }
+
+    // Replaces all projectiles, for mirroring a simulation like 'set_blob'.
+    pub fn set_projectiles(&mut self, projectiles: Vec<Projectile>) {
+        self.projectiles = projectiles;
+    }
+
+    fn sync_grid(&mut self) {
+        let blobs = &self.blobs;
+        let blob_indices = &self.blob_indices;
+        self.blob_grid
+            .for_each_mut(|entry| *entry = GridBlob::new(&blobs[blob_indices[&entry.id]]));
+        self.blob_grid.update();
+    }
+
+    // Pellets touching a blob are eaten by it, a pellet touching two blobs
+    // goes to the one that comes first.
+    fn eat_food(&mut self, events: &mut Vec<Event>) {
+        if self.food_count == 0 {
+            return;
+        }
+        let mut eaten_count = 0;
+        for blob in &mut self.blobs {
+            let reach = PointF32::new(blob.circle.radius, blob.circle.radius);
+            let aabb = Rect::new(blob.circle.pos - reach, blob.circle.pos + reach);
+            let reach_sqr = (blob.circle.radius + FOOD_RADIUS) * (blob.circle.radius + FOOD_RADIUS);
+            let mut eaten = 0u32;
+            self.food.for_objects_mut(&aabb, |food| {
+                let delta = food.pos - blob.circle.pos;
+                if !food.eaten && delta.dot(&delta) < reach_sqr {
+                    food.eaten = true;
+                    eaten += 1;
+                }
+            });
+            if eaten > 0 {
+                blob.grow(
+                    FOOD_RADIUS * (eaten as f32).sqrt(),
+                    FOOD_MASS * eaten as f32,
+                );
+                events.push(Event::Eat((blob.id, eaten)));
+                eaten_count += eaten as usize;
+            }
+        }
+        if eaten_count > 0 {
+            self.food.remove_if(|food| food.eaten);
+            self.food_count -= eaten_count;
+        }
+    }
+
+    fn can_absorb(blob: &Blob, other: &Blob) -> bool {
+        blob.owner != other.owner && blob.circle.radius >= other.circle.radius * ABSORB_RATIO
+    }
+
+    fn can_merge(blob: &Blob, other: &Blob) -> bool {
+        blob.owner == other.owner && blob.merge_timer <= 0.0 && other.merge_timer <= 0.0
+    }
+
+    // The blobs of 'owner' in id order.
+    pub fn owned_blobs(&self, owner: OwnerId) -> Vec<BlobId> {
+        let mut blob_ids: Vec<BlobId> = self
+            .blobs
+            .iter()
+            .filter(|blob| blob.owner == owner)
+            .map(|blob| blob.id)
+            .collect();
+        blob_ids.sort_unstable();
+        blob_ids
+    }
+
+    // Halves the blob and launches the new half along 'direction', returns
+    // its id. Blobs that are too small or whose owner has too many blobs
+    // already don't split.
+    fn split_blob(&mut self, blob_id: BlobId, direction: PointF32) -> Option<BlobId> {
+        let blob = self.find_blob(blob_id)?;
+        let owner = blob.owner;
+        if blob.circle.radius < SPLIT_MIN_RADIUS
+            || self.blobs.iter().filter(|blob| blob.owner == owner).count() >= MAX_FRAGMENTS
+        {
+            return None;
+        }
+
+        let half_id = self.allocate_blob_id();
+        let blob = self.find_blob_mut(blob_id).unwrap();
+        // Half the area.
+        blob.circle.radius *= std::f32::consts::FRAC_1_SQRT_2;
+        blob.mass *= 0.5;
+        blob.merge_timer = MERGE_TIME;
+
+        let mut half = blob.clone();
+        half.id = half_id;
+        half.circle.pos += direction * (2.0 * half.circle.radius);
+        half.velocity += direction * SPLIT_SPEED;
+        self.add_blob(half);
+        self.events.push(Event::Split((blob_id, half_id)));
+        Some(half_id)
+    }
+
+    // Blobs that can merge are pulled towards the center of mass of the
+    // other blobs of their owner.
+    fn pull_fragments(&mut self, dt: f32) {
+        let mut centers = HashMap::<OwnerId, (PointF32, f32, usize)>::new();
+        for blob in self.blobs.iter().filter(|blob| blob.merge_timer <= 0.0) {
+            let center = centers
+                .entry(blob.owner)
+                .or_insert((PointF32::new(0.0, 0.0), 0.0, 0));
+            center.0 += blob.circle.pos * blob.mass;
+            center.1 += blob.mass;
+            center.2 += 1;
+        }
+
+        for blob in &mut self.blobs {
+            if blob.merge_timer > 0.0 {
+                continue;
+            }
+            let (weighted_pos, mass, count) = centers[&blob.owner];
+            if count < 2 {
+                continue;
+            }
+            let delta = weighted_pos / mass - blob.circle.pos;
+            let distance = delta.magnitude();
+            if distance > f32::EPSILON {
+                blob.force += delta / distance * MERGE_PULL * blob.mass / DEFAULT_MASS * dt;
+            }
+        }
+    }
+
+    // Blobs that are far enough inside a larger one are eaten by it, the
+    // larger blob gets their area, mass and momentum. Blobs of the same
+    // owner merge the same way once their merge timers have run out.
+    fn absorb_blobs(&mut self, events: &mut Vec<Event>) {
+        let mut absorptions = Vec::<(BlobId, BlobId)>::new();
         for blob in &self.blobs {
-            grid.for_objects(&blob.collision_aabb, |other| {
-                if !std::ptr::eq(blob, other.blob) && blob.id < other.blob.id {
-                    // Here we can do "narrow" collision detection
+            let reach = PointF32::new(blob.circle.radius, blob.circle.radius);
+            let aabb = Rect::new(blob.circle.pos - reach, blob.circle.pos + reach);
+            self.blob_grid.for_objects(&aabb, |entry| {
+                let other = &self.blobs[self.blob_indices[&entry.id]];
+                let core =
+                    Circle::new_from_pos(other.circle.pos, other.circle.radius * ABSORB_DEPTH);
+                let absorbs = Simulation::can_absorb(blob, other) && blob.circle.contains(&core);
+                // A blob merges once the center of the other is inside it,
+                // the larger one (or the older one of two of the same size)
+                // is kept.
+                let larger = (blob.circle.radius, other.id) > (other.circle.radius, blob.id);
+                let merges = Simulation::can_merge(blob, other)
+                    && larger
+                    && blob.circle.contains_pos(&other.circle.pos);
+                if absorbs || merges {
+                    absorptions.push((blob.id, other.id));
                 }
             });
         }
 
-        Vec::new()
+        // The largest blobs eat first, a blob that has been eaten doesn't
+        // eat anything else in the same step. Ties are broken by id so the
+        // order doesn't depend on where the blobs are stored.
+        let radius = |blob_id: &BlobId| self.blobs[self.blob_indices[blob_id]].circle.radius;
+        absorptions.sort_by(|a, b| {
+            radius(&b.0)
+                .total_cmp(&radius(&a.0))
+                .then(a.0.cmp(&b.0))
+                .then(a.1.cmp(&b.1))
+        });
+
+        for (blob_id, other_id) in absorptions {
+            if self.find_blob(blob_id).is_none() {
+                continue;
+            }
+            let other = match self.despawn(other_id) {
+                Some(other) => other,
+                None => continue,
+            };
+            let blob = self.find_blob_mut(blob_id).unwrap();
+            let mass = blob.mass + other.mass;
+            blob.velocity = (blob.velocity * blob.mass + other.velocity * other.mass) / mass;
+            blob.grow(other.circle.radius, other.mass);
+            if blob.owner == other.owner {
+                events.push(Event::Merge((blob_id, other_id)));
+            } else {
+                events.push(Event::Absorb((blob_id, other_id)));
+            }
+        }
+
+        // The grid has to cover the new radius of the blobs that grew.
+        self.sync_grid();
+    }
+
+    // The impulse above the threshold is shared out by mass, the lighter
+    // blob takes more of the damage.
+    fn deal_damage(first: &mut Blob, second: &mut Blob, impulse: f32, threshold: f32, scale: f32) {
+        if impulse <= threshold {
+            return;
+        }
+        let damage = (impulse - threshold) * scale;
+        let total_mass = first.mass + second.mass;
+        first.health -= damage * second.mass / total_mass;
+        second.health -= damage * first.mass / total_mass;
+    }
+
+    fn bounce_axis(
+        pos: &mut f32,
+        velocity: &mut f32,
+        min: f32,
+        max: f32,
+        dt: f32,
+        restitution: f32,
+    ) -> Option<(f32, f32)> {
+        // Returns the time of impact and which side (-1 or 1) was hit, the
+        // blob is moved back along its reflected velocity the same way as
+        // in 'resolve_contact'.
+        let end = *pos + *velocity * dt;
+        let (wall, side) = if end < min && *velocity <= 0.0 {
+            (min, -1.0)
+        } else if end > max && *velocity >= 0.0 {
+            (max, 1.0)
+        } else {
+            return None;
+        };
+
+        let t = if velocity.abs() > f32::EPSILON {
+            ((wall - *pos) / *velocity).max(0.0).min(dt)
+        } else {
+            0.0
+        };
+        *velocity = -*velocity * restitution;
+        *pos = wall - *velocity * t;
+
+        Some((t, side))
+    }
+
+    fn bounce_off_walls(
+        rect: &Rect,
+        blob: &mut Blob,
+        dt: f32,
+        restitution: f32,
+    ) -> Option<PointF32> {
+        let radius = blob.circle.radius;
+        let start = blob.circle.pos;
+        let velocity = blob.velocity;
+
+        let x_hit = Simulation::bounce_axis(
+            &mut blob.circle.pos.x,
+            &mut blob.velocity.x,
+            rect.bottom_left.x + radius,
+            rect.top_right.x - radius,
+            dt,
+            restitution,
+        );
+        let y_hit = Simulation::bounce_axis(
+            &mut blob.circle.pos.y,
+            &mut blob.velocity.y,
+            rect.bottom_left.y + radius,
+            rect.top_right.y - radius,
+            dt,
+            restitution,
+        );
+
+        // The contact point is on the wall that was hit first.
+        let x_first = match (x_hit, y_hit) {
+            (Some((x_t, _)), Some((y_t, _))) => x_t <= y_t,
+            (Some(_), None) => true,
+            (None, Some(_)) => false,
+            (None, None) => return None,
+        };
+
+        if x_first {
+            let (t, side) = x_hit.unwrap();
+            let center = start + velocity * t;
+            let x = if side < 0.0 {
+                rect.bottom_left.x
+            } else {
+                rect.top_right.x
+            };
+            Some(PointF32::new(x, center.y))
+        } else {
+            let (t, side) = y_hit.unwrap();
+            let center = start + velocity * t;
+            let y = if side < 0.0 {
+                rect.bottom_left.y
+            } else {
+                rect.top_right.y
+            };
+            Some(PointF32::new(center.x, y))
+        }
+    }
+
+    // Pushes a blob that overlaps 'obstacle' out of it and reflects the part
+    // of its velocity going into the obstacle, so it slides along it.
+    // Returns where it touches the obstacle if it bounced, like the walls a
+    // blob resting or sliding on it isn't reported.
+    fn bounce_off_obstacle(obstacle: &Rect, blob: &mut Blob, restitution: f32) -> Option<PointF32> {
+        let contact = collision::collides_rect(&blob.circle, obstacle)?;
+        blob.circle.pos += contact.normal * contact.penetration;
+        let normal_speed = blob.velocity.dot(&contact.normal);
+        if normal_speed >= 0.0 {
+            return None;
+        }
+        blob.velocity -= contact.normal * (normal_speed * (1.0 + restitution));
+        Some(blob.circle.pos - contact.normal * blob.circle.radius)
+    }
+
+    fn resolve_contact(
+        first: &mut Blob,
+        second: &mut Blob,
+        t: f32,
+        restitution: f32,
+    ) -> Option<(PointF32, f32)> {
+        // Both blobs are moved to the time of impact, the impulse is applied
+        // along the line between their centers and then they are moved back
+        // along their new velocities, so that 'advance' puts them where they
+        // would be after bouncing.
+        let first_pos = first.circle.pos + first.velocity * t;
+        let second_pos = second.circle.pos + second.velocity * t;
+
+        let delta = second_pos - first_pos;
+        let distance = delta.magnitude();
+        if distance <= f32::EPSILON {
+            return None;
+        }
+        let normal = delta / distance;
+
+        let closing_velocity = (second.velocity - first.velocity).dot(&normal);
+        if closing_velocity >= 0.0 {
+            // Already moving apart.
+            return None;
+        }
+
+        let impulse =
+            -(1.0 + restitution) * closing_velocity / (1.0 / first.mass + 1.0 / second.mass);
+
+        first.velocity -= normal * (impulse / first.mass);
+        second.velocity += normal * (impulse / second.mass);
+
+        first.circle.pos = first_pos - first.velocity * t;
+        second.circle.pos = second_pos - second.velocity * t;
+
+        Some((first_pos + normal * first.circle.radius, impulse))
     }
 
     pub fn objects(&self, rect: Rect) -> Vec<&Blob> {
-        self.blobs.iter().collect()
+        let mut objects = Vec::<&Blob>::new();
+        self.blob_grid.for_objects(&rect, |entry| {
+            objects.push(&self.blobs[self.blob_indices[&entry.id]]);
+        });
+        objects
+    }
+
+    pub fn food(&self, rect: Rect) -> Vec<Food> {
+        let mut food = Vec::<Food>::new();
+        self.food.for_objects(&rect, |pellet| food.push(*pellet));
+        food
+    }
+}
+
+impl Default for Simulation {
+    fn default() -> Self {
+        Simulation::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::projectile::PROJECTILE_LIFETIME;
+    use super::*;
+
+    fn simulation_with(blobs: Vec<Blob>) -> Simulation {
+        let mut simulation = Simulation::new_empty(Rect::new(
+            PointF32::new(-1000.0, -1000.0),
+            PointF32::new(1000.0, 1000.0),
+        ));
+        for blob in blobs {
+            simulation.add_blob(blob);
+        }
+        simulation.events.clear();
+        simulation
+    }
+
+    #[test]
+    fn test_blobs_bounce() {
+        let mut first = Blob::new(100.0, 100.0, 10.0, 0);
+        first.velocity = PointF32::new(100.0, 0.0);
+        let mut second = Blob::new(125.0, 100.0, 10.0, 1);
+        second.velocity = PointF32::new(-100.0, 0.0);
+
+        let mut simulation = simulation_with(vec![first, second]);
+        let events = simulation.simulate(&HashMap::new(), 0.1);
+
+        assert_eq!(events.len(), 1);
+        match events[0] {
+            Event::Collision((0, 1, contact, impulse)) => {
+                assert!((contact.y - 100.0).abs() < 0.001);
+                assert!(impulse > 0.0);
+            }
+            _ => panic!(),
+        }
+
+        let first = &simulation.blobs[0];
+        let second = &simulation.blobs[1];
+        assert!(first.velocity.x < 0.0);
+        assert!(second.velocity.x > 0.0);
+        assert!(second.circle.pos.x - first.circle.pos.x >= 20.0);
+    }
+
+    #[test]
+    fn test_heavier_blob_moves_less() {
+        let mut light = Blob::new(100.0, 100.0, 10.0, 0);
+        light.velocity = PointF32::new(100.0, 0.0);
+        let mut heavy = Blob::new(125.0, 100.0, 10.0, 1);
+        heavy.mass *= 4.0;
+
+        let mut simulation = simulation_with(vec![light, heavy]);
+        simulation.simulate(&HashMap::new(), 0.1);
+
+        let light = &simulation.blobs[0];
+        let heavy = &simulation.blobs[1];
+        assert!(light.velocity.x < 0.0);
+        assert!(heavy.velocity.x > 0.0);
+        assert!(heavy.velocity.x < -light.velocity.x);
+
+        // Drag is applied before the contact, so momentum after the bounce
+        // is compared to the momentum the light blob had going into it.
+        let momentum = light.velocity.x * light.mass + heavy.velocity.x * heavy.mass;
+        assert!(momentum > 0.0 && momentum < 100.0 * light.mass);
+    }
+
+    #[test]
+    fn test_overlapping_blobs_bounce() {
+        // Already overlapping and moving into each other, they are pushed
+        // apart instead of passing through each other.
+        let mut first = Blob::new(100.0, 100.0, 10.0, 0);
+        first.velocity = PointF32::new(50.0, 0.0);
+        let mut second = Blob::new(115.0, 100.0, 10.0, 1);
+        second.velocity = PointF32::new(-50.0, 0.0);
+
+        let mut simulation = simulation_with(vec![first, second]);
+        let events = simulation.simulate(&HashMap::new(), 0.01);
+
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, Event::Collision(_))));
+        assert!(simulation.blobs[0].velocity.x < 0.0);
+        assert!(simulation.blobs[1].velocity.x > 0.0);
+    }
+
+    #[test]
+    fn test_blob_pushes_blob_ahead() {
+        // Blob 0 keeps driving into blob 1 and pushes it along. They start a
+        // little inside each other, like touching blobs can end up after
+        // rounding, blob 0 still doesn't get through.
+        let mut simulation = simulation_with(vec![
+            Blob::new(100.0, 100.0, 10.0, 0),
+            Blob::new(119.9, 100.0, 10.0, 1),
+        ]);
+        let mut inputs = HashMap::new();
+        inputs.insert(0, vec![Action::new(InputEvent::Forward, 1.0)]);
+        for _ in 0..300 {
+            simulation.simulate(&inputs, 1.0 / 60.0);
+            let pusher = simulation.find_blob(0).unwrap().circle.pos;
+            let pushed = simulation.find_blob(1).unwrap().circle.pos;
+            assert!(pushed.x - pusher.x > 19.0);
+        }
+        assert!(simulation.find_blob(1).unwrap().circle.pos.x > 300.0);
+    }
+
+    #[test]
+    fn test_blob_bounces_off_wall() {
+        let mut blob = Blob::new(985.0, 0.0, 10.0, 0);
+        blob.velocity = PointF32::new(100.0, 0.0);
+
+        let mut simulation = simulation_with(vec![blob]);
+        simulation.set_wall_restitution(1.0);
+        let events = simulation.simulate(&HashMap::new(), 0.1);
+
+        let blob = &simulation.blobs[0];
+        assert!(blob.velocity.x < 0.0);
+        assert!(blob.circle.pos.x + blob.circle.radius <= 1000.0);
+        assert_eq!(events.len(), 1);
+        match events[0] {
+            Event::WallHit((blob_id, contact)) => {
+                assert_eq!(blob_id, 0);
+                assert_eq!(contact.x, 1000.0);
+                assert_eq!(contact.y, 0.0);
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_blob_slides_along_obstacle() {
+        let mut blob = Blob::new(0.0, 15.0, 10.0, 0);
+        blob.velocity = PointF32::new(100.0, -100.0);
+
+        let mut simulation = simulation_with(vec![blob]);
+        simulation.set_wall_restitution(0.0);
+        simulation.add_obstacle(Rect::new(
+            PointF32::new(-100.0, -100.0),
+            PointF32::new(100.0, 0.0),
+        ));
+        let events = simulation.simulate(&HashMap::new(), 0.1);
+
+        // Pushed out on top of the obstacle, keeping the speed along it.
+        let blob = &simulation.blobs[0];
+        assert_eq!(blob.circle.pos.y, 10.0);
+        assert_eq!(blob.velocity.y, 0.0);
+        assert!(blob.velocity.x > 50.0);
+        assert_eq!(
+            events,
+            vec![Event::WallHit((0, PointF32::new(blob.circle.pos.x, 0.0)))]
+        );
+
+        // Sliding on it is no new hit.
+        for _ in 0..5 {
+            let events = simulation.simulate(&HashMap::new(), 0.1);
+            assert!(events.is_empty());
+            assert_eq!(simulation.blobs[0].circle.pos.y, 10.0);
+        }
+    }
+
+    #[test]
+    fn test_projectile_stops_at_obstacle() {
+        let shooter = Blob::new(0.0, 0.0, 10.0, 0);
+        let target = Blob::new(100.0, 0.0, 10.0, 1);
+        let mut simulation = simulation_with(vec![shooter, target]);
+        simulation.add_obstacle(Rect::new(
+            PointF32::new(50.0, -20.0),
+            PointF32::new(51.0, 20.0),
+        ));
+
+        let mut events = simulation.simulate(&owner_inputs(0, InputEvent::Fire), 0.01);
+        for _ in 0..20 {
+            events.extend(simulation.simulate(&HashMap::new(), 0.01));
+        }
+
+        assert!(!events.iter().any(|event| matches!(event, Event::Damage(_))));
+        assert!(simulation.projectiles().is_empty());
+    }
+
+    #[test]
+    fn test_obstacle_shields_blob_in_same_step() {
+        let shooter = Blob::new(0.0, 0.0, 10.0, 0);
+        let target = Blob::new(62.0, 0.0, 10.0, 1);
+        let mut simulation = simulation_with(vec![shooter, target]);
+        simulation.add_obstacle(Rect::new(
+            PointF32::new(50.0, -20.0),
+            PointF32::new(51.0, 20.0),
+        ));
+
+        // Long steps, so the projectile would reach both the obstacle and
+        // the blob behind it in one step.
+        let mut events = simulation.simulate(&owner_inputs(0, InputEvent::Fire), 0.05);
+        for _ in 0..4 {
+            events.extend(simulation.simulate(&HashMap::new(), 0.05));
+        }
+
+        assert!(!events.iter().any(|event| matches!(event, Event::Damage(_))));
+        assert!(simulation.projectiles().is_empty());
+    }
+
+    #[test]
+    fn test_projectile_passes_beside_obstacle() {
+        let shooter = Blob::new(0.0, 0.0, 10.0, 0);
+        let mut simulation = simulation_with(vec![shooter]);
+        // Closer to its path than it moves in a step, but not touching it.
+        simulation.add_obstacle(Rect::new(
+            PointF32::new(50.0, 3.0),
+            PointF32::new(51.0, 20.0),
+        ));
+
+        simulation.simulate(&owner_inputs(0, InputEvent::Fire), 0.01);
+        for _ in 0..10 {
+            simulation.simulate(&HashMap::new(), 0.01);
+        }
+
+        assert_eq!(simulation.projectiles().len(), 1);
+        assert!(simulation.projectiles()[0].circle.pos.x > 51.0);
+    }
+
+    #[test]
+    fn test_no_food_in_obstacles() {
+        let obstacle = Rect::new(PointF32::new(-500.0, -500.0), PointF32::new(500.0, 500.0));
+        let mut simulation = simulation_with(Vec::new());
+        simulation.add_obstacle(obstacle);
+        simulation.set_food(500, 7);
+
+        let mut inside = obstacle;
+        inside.grow(-FOOD_RADIUS, -FOOD_RADIUS);
+        assert!(simulation.food(inside).is_empty());
+        assert_eq!(simulation.food_count(), 500);
+    }
+
+    #[test]
+    fn test_blob_outside_arena_is_pushed_back() {
+        let mut blob = Blob::new(-1500.0, 1200.0, 10.0, 0);
+        blob.velocity = PointF32::new(-10.0, 10.0);
+
+        let mut simulation = simulation_with(vec![blob]);
+        simulation.simulate(&HashMap::new(), 0.1);
+
+        let blob = &simulation.blobs[0];
+        assert!(blob.velocity.x > 0.0);
+        assert!(blob.velocity.y < 0.0);
+        assert!(blob.circle.pos.x - blob.circle.radius >= -1000.0);
+        assert!(blob.circle.pos.y + blob.circle.radius <= 1000.0);
+    }
+
+    #[test]
+    fn test_spawn_events() {
+        let mut simulation = Simulation::new();
+        let events = simulation.simulate(&HashMap::new(), 0.1);
+
+        assert_eq!(
+            events,
+            vec![
+                Event::Spawn((0, PointF32::new(100.0, 100.0))),
+                Event::Spawn((1, PointF32::new(140.0, 100.0))),
+            ]
+        );
+        assert!(simulation.simulate(&HashMap::new(), 0.1).is_empty());
+    }
+
+    #[test]
+    fn test_spawn_and_despawn() {
+        let mut simulation = Simulation::new();
+        simulation.simulate(&HashMap::new(), 0.1);
+
+        let blob_id = simulation.spawn_blob(&BlobDesc::new(-300.0, 200.0, 15.0));
+        assert_eq!(blob_id, 2);
+        assert_eq!(simulation.find_blob(blob_id).unwrap().circle.radius, 15.0);
+
+        let removed = simulation.despawn(0).unwrap();
+        assert_eq!(removed.id, 0);
+        assert!(simulation.find_blob(0).is_none());
+        assert!(simulation.despawn(0).is_none());
+
+        // The blob that was moved into the removed slot can still be found.
+        assert_eq!(simulation.find_blob(blob_id).unwrap().id, blob_id);
+        assert_eq!(simulation.find_blob_mut(1).unwrap().id, 1);
+
+        assert_eq!(simulation.spawn_blob(&BlobDesc::new(0.0, 0.0, 5.0)), 3);
+
+        let events = simulation.simulate(&HashMap::new(), 0.1);
+        assert_eq!(
+            events,
+            vec![
+                Event::Spawn((2, PointF32::new(-300.0, 200.0))),
+                Event::Remove(0),
+                Event::Spawn((3, PointF32::new(0.0, 0.0))),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_blob_ids_skip_live_ids_on_wrap() {
+        let mut simulation = simulation_with(vec![Blob::new(0.0, 0.0, 1.0, 0)]);
+        simulation.next_blob_id = BlobId::MAX;
+
+        assert_eq!(
+            simulation.spawn_blob(&BlobDesc::new(0.0, 0.0, 1.0)),
+            BlobId::MAX
+        );
+        assert_eq!(simulation.spawn_blob(&BlobDesc::new(0.0, 0.0, 1.0)), 1);
+    }
+
+    #[test]
+    fn test_objects_in_rect() {
+        let mut simulation = Simulation::new();
+        let far_away = simulation.spawn_blob(&BlobDesc::new(-800.0, 900.0, 10.0));
+
+        let ids = |objects: Vec<&Blob>| {
+            let mut ids: Vec<BlobId> = objects.iter().map(|blob| blob.id).collect();
+            ids.sort();
+            ids
+        };
+
+        let view = Rect::new(PointF32::new(0.0, 0.0), PointF32::new(200.0, 200.0));
+        assert_eq!(ids(simulation.objects(view)), vec![0, 1]);
+        assert_eq!(
+            ids(simulation.objects(simulation.rect)),
+            vec![0, 1, far_away]
+        );
+
+        simulation.find_blob_mut(0).unwrap().circle.pos = PointF32::new(-500.0, 100.0);
+        simulation.simulate(&HashMap::new(), 0.01);
+        assert_eq!(ids(simulation.objects(view)), vec![1]);
+
+        simulation.despawn(1);
+        assert!(simulation.objects(view).is_empty());
+    }
+
+    #[test]
+    fn test_deterministic_runs_agree() {
+        let run = || {
+            let mut simulation = Simulation::new();
+            simulation.set_deterministic(true);
+            let mut hashes = Vec::<u64>::new();
+            for step in 0..300 {
+                // A new map each step, so the iteration order varies.
+                let mut inputs = HashMap::<BlobId, Vec<Action>>::new();
+                inputs.insert(
+                    1,
+                    vec![
+                        Action::new(InputEvent::TurnRight, 0.5),
+                        Action::new(InputEvent::Forward, 1.0),
+                    ],
+                );
+                if step < 120 {
+                    inputs.insert(0, vec![Action::new(InputEvent::Forward, 1.0)]);
+                }
+                simulation.simulate(&inputs, 1.0 / 60.0);
+                hashes.push(simulation.state_hash());
+            }
+            hashes
+        };
+
+        let first = run();
+        assert_eq!(first, run());
+        assert_eq!(first.len(), 300);
+        assert_ne!(first[0], first[299]);
+    }
+
+    #[test]
+    fn test_state_hash_changes_with_state() {
+        let mut simulation = Simulation::new();
+        let hash = simulation.state_hash();
+        assert_eq!(hash, Simulation::new().state_hash());
+
+        simulation.find_blob_mut(1).unwrap().velocity.x = 1.0;
+        assert_ne!(hash, simulation.state_hash());
+    }
+
+    #[test]
+    fn test_collision_damage() {
+        let mut first = Blob::new(100.0, 100.0, 10.0, 0);
+        first.velocity = PointF32::new(100.0, 0.0);
+        let mut second = Blob::new(125.0, 100.0, 10.0, 1);
+        second.velocity = PointF32::new(-100.0, 0.0);
+        second.mass *= 3.0;
+
+        let mut simulation = simulation_with(vec![first, second]);
+        simulation.simulate(&HashMap::new(), 0.1);
+
+        let first = simulation.find_blob(0).unwrap();
+        let second = simulation.find_blob(1).unwrap();
+        assert!(first.health < 100.0);
+        assert!(second.health < 100.0);
+        // The lighter blob takes three times the damage.
+        let ratio = (100.0 - first.health) / (100.0 - second.health);
+        assert!((ratio - 3.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_light_touch_does_no_damage() {
+        let mut first = Blob::new(100.0, 100.0, 10.0, 0);
+        first.velocity = PointF32::new(10.0, 0.0);
+        let second = Blob::new(120.5, 100.0, 10.0, 1);
+
+        let mut simulation = simulation_with(vec![first, second]);
+        let events = simulation.simulate(&HashMap::new(), 0.1);
+
+        assert!(matches!(events[..], [Event::Collision(_)]));
+        assert!(simulation.blobs().iter().all(|blob| blob.health == 100.0));
+    }
+
+    #[test]
+    fn test_blob_without_health_is_killed() {
+        let mut first = Blob::new(100.0, 100.0, 10.0, 0);
+        first.velocity = PointF32::new(100.0, 0.0);
+        first.health = 1.0;
+        let mut second = Blob::new(125.0, 100.0, 10.0, 1);
+        second.velocity = PointF32::new(-100.0, 0.0);
+
+        let mut simulation = simulation_with(vec![first, second]);
+        let events = simulation.simulate(&HashMap::new(), 0.1);
+
+        assert!(simulation.find_blob(0).is_none());
+        assert!(simulation.find_blob(1).is_some());
+        assert_eq!(events.len(), 3);
+        match (&events[1], &events[2]) {
+            (Event::Kill((pos, radius)), Event::Remove(0)) => {
+                assert!((pos.x - 100.0).abs() < 10.0);
+                assert_eq!(*radius, 10.0);
+            }
+            _ => panic!("{:?}", events),
+        }
+        assert!(simulation.simulate(&HashMap::new(), 0.1).is_empty());
+    }
+
+    #[test]
+    fn test_larger_blob_absorbs_smaller() {
+        let mut large = Blob::new(100.0, 100.0, 30.0, 0);
+        large.velocity = PointF32::new(10.0, 0.0);
+        let small = Blob::new(115.0, 100.0, 10.0, 1);
+
+        let mut simulation = simulation_with(vec![large, small]);
+        let events = simulation.simulate(&HashMap::new(), 0.1);
+
+        assert_eq!(events, vec![Event::Absorb((0, 1)), Event::Remove(1)]);
+        assert_eq!(simulation.blobs().len(), 1);
+        let large = simulation.find_blob(0).unwrap();
+        assert!((large.circle.radius - 1000f32.sqrt()).abs() < 0.001);
+        assert_eq!(large.mass, 26.0);
+        // Momentum is kept, the velocity is shared with the eaten blob.
+        assert!(large.velocity.x > 0.0 && large.velocity.x < 10.0);
+        assert_eq!(simulation.objects(simulation.rect()).len(), 1);
+    }
+
+    #[test]
+    fn test_absorbing_blob_moves_over_smaller() {
+        let mut large = Blob::new(100.0, 100.0, 30.0, 0);
+        large.velocity = PointF32::new(200.0, 0.0);
+        let small = Blob::new(145.0, 100.0, 10.0, 1);
+
+        let mut simulation = simulation_with(vec![large, small]);
+        // The blobs don't bounce, the large one keeps going until it has
+        // the small one inside it.
+        let mut events = Vec::new();
+        for _ in 0..10 {
+            events.extend(simulation.simulate(&HashMap::new(), 0.02));
+        }
+
+        assert!(events.contains(&Event::Absorb((0, 1))));
+        assert!(!events
+            .iter()
+            .any(|event| matches!(event, Event::Collision(_))));
+        assert!(simulation.find_blob(1).is_none());
+    }
+
+    #[test]
+    fn test_similar_blobs_dont_absorb() {
+        let large = Blob::new(100.0, 100.0, 12.0, 0);
+        let small = Blob::new(103.0, 100.0, 10.0, 1);
+
+        let mut simulation = simulation_with(vec![large, small]);
+        simulation.simulate(&HashMap::new(), 0.1);
+
+        assert_eq!(simulation.blobs().len(), 2);
+    }
+
+    #[test]
+    fn test_heavy_blob_is_slower() {
+        let light = Blob::new(-500.0, 0.0, 10.0, 0);
+        let mut heavy = Blob::new(-500.0, 500.0, 10.0, 1);
+        heavy.mass *= 4.0;
+
+        let mut simulation = simulation_with(vec![light, heavy]);
+        let mut inputs = HashMap::new();
+        inputs.insert(0, vec![Action::new(InputEvent::Forward, 1.0)]);
+        inputs.insert(1, vec![Action::new(InputEvent::Forward, 1.0)]);
+        for _ in 0..60 {
+            simulation.simulate(&inputs, 1.0 / 60.0);
+        }
+
+        let light = simulation.find_blob(0).unwrap();
+        let heavy = simulation.find_blob(1).unwrap();
+        assert!(heavy.velocity.x < light.velocity.x * 0.5);
+    }
+
+    #[test]
+    fn test_blob_eats_food() {
+        let mut simulation = simulation_with(vec![Blob::new(0.0, 0.0, 10.0, 0)]);
+        simulation.set_food(3, 1);
+        simulation.max_food = 0;
+        // Replace the random pellets with two next to the blob and one far
+        // away.
+        simulation.food = LooseGrid::new(simulation.rect, 20, 20);
+        for pos in &[
+            PointF32::new(11.0, 0.0),
+            PointF32::new(0.0, -11.0),
+            PointF32::new(500.0, 500.0),
+        ] {
+            simulation.food.add(Food::new(*pos));
+        }
+
+        let events = simulation.simulate(&HashMap::new(), 0.1);
+
+        assert_eq!(events, vec![Event::Eat((0, 2))]);
+        assert_eq!(simulation.food_count(), 1);
+        assert_eq!(simulation.food(simulation.rect()).len(), 1);
+        let blob = simulation.find_blob(0).unwrap();
+        assert!((blob.circle.radius - 108f32.sqrt()).abs() < 0.001);
+        assert_eq!(blob.mass, DEFAULT_MASS + 2.0 * FOOD_MASS);
+    }
+
+    #[test]
+    fn test_food_respawns() {
+        let mut simulation = Simulation::new();
+        simulation.set_deterministic(true);
+        // Blob 0 drives right until the wall, on the way it has to cross
+        // food and not get stuck at an obstacle. Blob 1 turns next to its
+        // lane instead of right in front of it, where blob 0 would push it
+        // ahead and it would eat the food first.
+        simulation.clear_obstacles();
+        let mut blob = simulation.find_blob(1).unwrap().clone();
+        blob.circle.pos = PointF32::new(100.0, 140.0);
+        blob.previous_pos = blob.circle.pos;
+        simulation.set_blob(blob);
+        let rect = simulation.rect();
+        let (max_food, _) = simulation.food_settings();
+        assert!(max_food > 0);
+        assert_eq!(simulation.food_count(), max_food);
+        assert_eq!(simulation.food(rect).len(), max_food);
+        assert!(simulation.food(rect).iter().all(|food| {
+            food.pos.x >= rect.bottom_left.x + FOOD_RADIUS
+                && food.pos.x <= rect.top_right.x - FOOD_RADIUS
+                && food.pos.y >= rect.bottom_left.y + FOOD_RADIUS
+                && food.pos.y <= rect.top_right.y - FOOD_RADIUS
+        }));
+
+        let mut inputs = HashMap::new();
+        inputs.insert(0, vec![Action::new(InputEvent::Forward, 1.0)]);
+        inputs.insert(1, vec![Action::new(InputEvent::TurnLeft, 1.0)]);
+        let mut eaten = 0;
+        for _ in 0..600 {
+            for event in simulation.simulate(&inputs, 1.0 / 60.0) {
+                if let Event::Eat((_, count)) = event {
+                    eaten += count;
+                }
+            }
+            assert!(simulation.food_count() <= max_food);
+        }
+
+        assert!(eaten > 0);
+        assert_eq!(simulation.food_count(), max_food);
+        assert_eq!(simulation.food(rect).len(), max_food);
+        assert!(simulation.find_blob(0).unwrap().circle.radius > 10.0);
+    }
+
+    fn owner_inputs(owner: OwnerId, event: InputEvent) -> HashMap<OwnerId, Vec<Action>> {
+        let mut inputs = HashMap::new();
+        inputs.insert(owner, vec![Action::new(event, 1.0)]);
+        inputs
+    }
+
+    #[test]
+    fn test_split() {
+        let mut simulation = simulation_with(vec![Blob::new(0.0, 0.0, 20.0, 0)]);
+        let events = simulation.simulate(&owner_inputs(0, InputEvent::Split), 0.01);
+
+        assert!(events.contains(&Event::Split((0, 1))));
+        assert_eq!(simulation.owned_blobs(0), vec![0, 1]);
+        let blob = simulation.find_blob(0).unwrap();
+        let half = simulation.find_blob(1).unwrap();
+        for blob in &[blob, half] {
+            assert!((blob.circle.radius - 200f32.sqrt()).abs() < 0.001);
+            assert_eq!(blob.mass, DEFAULT_MASS / 2.0);
+            assert!(blob.merge_timer > 0.0);
+        }
+        // The new half is launched in the direction the blob faces.
+        assert!(half.circle.pos.x > blob.circle.pos.x + blob.circle.radius);
+        assert!(half.velocity.x > 200.0);
+        assert_eq!(half.circle.pos.y, 0.0);
+
+        // Both halves get the owner's inputs, the small ones don't split.
+        let events = simulation.simulate(&owner_inputs(0, InputEvent::Split), 0.01);
+        assert!(!events.iter().any(|event| matches!(event, Event::Split(_))));
+        simulation.simulate(&owner_inputs(0, InputEvent::TurnLeft), 0.01);
+        assert!(simulation.find_blob(0).unwrap().angular_velocity < 0.0);
+        assert!(simulation.find_blob(1).unwrap().angular_velocity < 0.0);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut first = Blob::new(0.0, 0.0, 10.0, 0);
+        let mut second = Blob::new(5.0, 0.0, 10.0, 1);
+        second.owner = 0;
+        first.merge_timer = 0.5;
+        second.merge_timer = 0.5;
+
+        // Blobs of the same owner don't merge before their timers run out,
+        // but they don't hurt each other either.
+        let mut simulation = simulation_with(vec![first, second]);
+        simulation.simulate(&HashMap::new(), 0.1);
+        assert_eq!(simulation.blobs().len(), 2);
+
+        let mut events = Vec::new();
+        for _ in 0..10 {
+            events.extend(simulation.simulate(&HashMap::new(), 0.1));
+        }
+        assert!(events.contains(&Event::Merge((0, 1))));
+        assert_eq!(simulation.owned_blobs(0), vec![0]);
+        let blob = simulation.find_blob(0).unwrap();
+        assert!((blob.circle.radius - 200f32.sqrt()).abs() < 0.001);
+        assert_eq!(blob.health, blob.max_health);
+    }
+
+    #[test]
+    fn test_split_halves_merge_back() {
+        let mut simulation = simulation_with(vec![Blob::new(0.0, 0.0, 20.0, 0)]);
+        simulation.set_deterministic(true);
+        simulation.simulate(&owner_inputs(0, InputEvent::Split), 1.0 / 60.0);
+        assert_eq!(simulation.blobs().len(), 2);
+
+        let mut events = Vec::new();
+        for _ in 0..(MERGE_TIME as usize + 10) * 60 {
+            events.extend(simulation.simulate(&HashMap::new(), 1.0 / 60.0));
+        }
+
+        assert!(events.contains(&Event::Merge((0, 1))));
+        assert_eq!(simulation.blobs().len(), 1);
+        let blob = simulation.find_blob(0).unwrap();
+        assert!((blob.circle.radius - 20.0).abs() < 0.001);
+        assert_eq!(blob.mass, DEFAULT_MASS);
+    }
+
+    #[test]
+    fn test_tuning_applies_to_next_step() {
+        let mut default = simulation_with(vec![Blob::new(0.0, 0.0, 10.0, 0)]);
+        let mut tuned = simulation_with(vec![Blob::new(0.0, 0.0, 10.0, 0)]);
+        let mut tuning = tuned.tuning();
+        tuning.thrust *= 2.0;
+        tuning.drag = 0.0;
+        tuned.set_tuning(tuning);
+
+        for _ in 0..10 {
+            default.simulate(&owner_inputs(0, InputEvent::Forward), 0.01);
+            tuned.simulate(&owner_inputs(0, InputEvent::Forward), 0.01);
+        }
+        assert!(tuned.blobs[0].velocity.x > default.blobs[0].velocity.x * 2.0);
+    }
+
+    #[test]
+    fn test_fire_cooldown() {
+        let mut simulation = simulation_with(vec![Blob::new(0.0, 0.0, 10.0, 0)]);
+        let events = simulation.simulate(&owner_inputs(0, InputEvent::Fire), 0.01);
+
+        assert_eq!(events, vec![Event::Shot((0, PointF32::new(12.0, 0.0)))]);
+        assert_eq!(simulation.projectiles().len(), 1);
+        let projectile = &simulation.projectiles()[0];
+        assert_eq!(projectile.shooter, 0);
+        assert_eq!(projectile.velocity, PointF32::new(PROJECTILE_SPEED, 0.0));
+
+        // Holding fire doesn't shoot again before the cooldown is over.
+        let mut shots = 0;
+        for _ in 0..(FIRE_COOLDOWN / 0.01) as usize + 1 {
+            let events = simulation.simulate(&owner_inputs(0, InputEvent::Fire), 0.01);
+            shots += events
+                .iter()
+                .filter(|event| matches!(event, Event::Shot(_)))
+                .count();
+        }
+        assert_eq!(shots, 1);
+        assert_eq!(simulation.projectiles().len(), 2);
+    }
+
+    #[test]
+    fn test_projectile_damages_blob() {
+        let shooter = Blob::new(0.0, 0.0, 10.0, 0);
+        let target = Blob::new(100.0, 0.0, 10.0, 1);
+        let mut simulation = simulation_with(vec![shooter, target]);
+
+        let mut events = simulation.simulate(&owner_inputs(0, InputEvent::Fire), 0.01);
+        for _ in 0..20 {
+            events.extend(simulation.simulate(&HashMap::new(), 0.01));
+        }
+
+        let damage: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                Event::Damage(damage) => Some(*damage),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(damage.len(), 1);
+        let (blob_id, contact, amount) = damage[0];
+        assert_eq!(blob_id, 1);
+        assert!((contact.x - 88.0).abs() < 0.5);
+        assert_eq!(amount, PROJECTILE_DAMAGE);
+
+        let target = simulation.find_blob(1).unwrap();
+        assert_eq!(target.health, target.max_health - PROJECTILE_DAMAGE);
+        assert!(target.velocity.x > 0.0);
+        assert!(simulation.projectiles().is_empty());
+    }
+
+    #[test]
+    fn test_projectile_passes_own_blobs() {
+        let shooter = Blob::new(0.0, 0.0, 10.0, 0);
+        let mut fragment = Blob::new(100.0, 0.0, 10.0, 1);
+        fragment.owner = 0;
+        let mut simulation = simulation_with(vec![shooter, fragment]);
+
+        let mut events = simulation.simulate(&owner_inputs(0, InputEvent::Fire), 0.01);
+        for _ in 0..20 {
+            events.extend(simulation.simulate(&HashMap::new(), 0.01));
+        }
+
+        assert!(!events.iter().any(|event| matches!(event, Event::Damage(_))));
+        let fragment = simulation.find_blob(1).unwrap();
+        assert_eq!(fragment.health, fragment.max_health);
+        // The fragment fired too.
+        assert_eq!(simulation.projectiles().len(), 2);
+    }
+
+    #[test]
+    fn test_projectile_expires() {
+        let mut simulation = simulation_with(vec![Blob::new(0.0, 0.0, 10.0, 0)]);
+        simulation.simulate(&owner_inputs(0, InputEvent::Fire), 0.01);
+        assert_eq!(simulation.projectiles().len(), 1);
+
+        // Slow it down so it runs out of time before leaving the arena.
+        simulation.projectiles[0].velocity = PointF32::new(0.0, 1.0);
+        for _ in 0..(PROJECTILE_LIFETIME / 0.1) as usize + 1 {
+            simulation.simulate(&HashMap::new(), 0.1);
+        }
+        assert!(simulation.projectiles().is_empty());
     }
 }
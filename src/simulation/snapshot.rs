@@ -0,0 +1,145 @@
+use super::blob::{Blob, BlobId};
+use super::food::Food;
+use super::projectile::Projectile;
+use super::simulation::Event;
+use super::tuning::Tuning;
+use crate::math::rect::Rect;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// The full state of a 'Simulation', taken with 'Simulation::snapshot' and
+// put back with 'Simulation::restore'. Saved as bytes it is
+//
+// "BLBRSNAP" version: u8
+//
+// followed by the bincode encoding of the snapshot.
+
+const MAGIC: &[u8; 8] = b"BLBRSNAP";
+const VERSION: u8 = 7;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Invalid(String),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Invalid(message) => write!(f, "invalid snapshot: {}", message),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub(super) rect: Rect,
+    pub(super) wall_restitution: f32,
+    pub(super) tuning: Tuning,
+    pub(super) obstacles: Vec<Rect>,
+    pub(super) damage_threshold: f32,
+    pub(super) damage_scale: f32,
+    // In the order of the simulation, it decides the order contacts are
+    // resolved in.
+    pub(super) blobs: Vec<Blob>,
+    // In the order of the grid, the same order they are added back in.
+    pub(super) food: Vec<Food>,
+    pub(super) max_food: usize,
+    pub(super) food_seed: u64,
+    pub(super) food_random: u64,
+    pub(super) projectiles: Vec<Projectile>,
+    pub(super) next_blob_id: BlobId,
+    pub(super) events: Vec<Event>,
+    pub(super) step: u64,
+    pub(super) deterministic: bool,
+}
+
+impl Snapshot {
+    pub fn step(&self) -> u64 {
+        self.step
+    }
+
+    pub fn blobs(&self) -> &[Blob] {
+        &self.blobs
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bincode::serialize_into(&mut bytes, self).expect("Cannot serialize snapshot");
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Snapshot, SnapshotError> {
+        if bytes.len() <= MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(SnapshotError::Invalid("not a snapshot".to_string()));
+        }
+        let version = bytes[MAGIC.len()];
+        if version != VERSION {
+            return Err(SnapshotError::Invalid(format!(
+                "unsupported version {}",
+                version
+            )));
+        }
+        bincode::deserialize(&bytes[MAGIC.len() + 1..])
+            .map_err(|error| SnapshotError::Invalid(error.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::blob::BlobDesc;
+    use crate::simulation::{Action, InputEvent, Simulation};
+    use std::collections::HashMap;
+
+    fn forward(blob_id: BlobId) -> HashMap<BlobId, Vec<Action>> {
+        let mut inputs = HashMap::new();
+        inputs.insert(blob_id, vec![Action::new(InputEvent::Forward, 1.0)]);
+        inputs
+    }
+
+    #[test]
+    fn test_restore_continues_the_same() {
+        let dt = 1.0 / 60.0;
+        let mut simulation = Simulation::new();
+        simulation.set_deterministic(true);
+        for _ in 0..50 {
+            simulation.simulate(&forward(0), dt);
+        }
+        // A blob waiting to be reported as spawned is part of the state too.
+        simulation.spawn_blob(&BlobDesc::new(-200.0, 0.0, 15.0));
+        let snapshot = simulation.snapshot();
+
+        let mut events = Vec::new();
+        for _ in 0..100 {
+            events.extend(simulation.simulate(&forward(1), dt));
+        }
+        let hash = simulation.state_hash();
+
+        let mut restored = Simulation::new_empty(Rect::new_empty());
+        restored.restore(&Snapshot::from_bytes(&snapshot.to_bytes()).unwrap());
+        assert_eq!(restored.snapshot(), snapshot);
+        let mut restored_events = Vec::new();
+        for _ in 0..100 {
+            restored_events.extend(restored.simulate(&forward(1), dt));
+        }
+
+        assert_eq!(restored.state_hash(), hash);
+        assert_eq!(restored_events, events);
+        // It has eaten some food since.
+        assert!(restored.find_blob(2).unwrap().circle.radius >= 15.0);
+        assert_eq!(restored.food_count(), simulation.food_count());
+    }
+
+    #[test]
+    fn test_from_bytes_errors() {
+        let bytes = Simulation::new().snapshot().to_bytes();
+
+        assert!(Snapshot::from_bytes(b"BLBRPLAY\x01").is_err());
+        assert!(Snapshot::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+        let mut version = bytes.clone();
+        version[MAGIC.len()] = 1;
+        assert!(Snapshot::from_bytes(&version).is_err());
+    }
+}
@@ -0,0 +1,89 @@
+use crate::ron_file::{from_ron, invalid, RonError};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+// How blobs move, kept apart from the rest of the simulation so it can be
+// tweaked while the game runs. A tuning file has it in RON, e.g.
+//
+// (thrust: 80000.0, drag: 0.3)
+//
+// where values that are left out keep their defaults.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Tuning {
+    // Force of a full Forward per second, Backward is half of it.
+    pub thrust: f32,
+    pub angle_thrust: f32,
+    // Drag grows with the square of the (angular) velocity.
+    pub drag: f32,
+    pub angular_drag: f32,
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Tuning {
+            thrust: 100000.0,
+            angle_thrust: 4000.0,
+            drag: 0.2,
+            angular_drag: 1000.0,
+        }
+    }
+}
+
+impl Tuning {
+    pub fn parse(source: &str) -> Result<Tuning, RonError> {
+        let tuning: Tuning = from_ron(source)?;
+        for (name, value) in [
+            ("thrust", tuning.thrust),
+            ("angle_thrust", tuning.angle_thrust),
+            ("drag", tuning.drag),
+            ("angular_drag", tuning.angular_drag),
+        ] {
+            if !value.is_finite() || value < 0.0 {
+                return Err(invalid(format!("{} can't be negative or infinite", name)));
+            }
+        }
+        Ok(tuning)
+    }
+
+    // Without a tuning file the defaults are used.
+    pub fn load(path: &Path) -> Result<Tuning, RonError> {
+        match std::fs::read_to_string(path) {
+            Ok(source) => Tuning::parse(&source),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(Tuning::default()),
+            Err(error) => Err(RonError::Io(error)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let tuning = Tuning::parse("(thrust: 80000.0, drag: 0.3)").unwrap();
+        assert_eq!(tuning.thrust, 80000.0);
+        assert_eq!(tuning.drag, 0.3);
+        assert_eq!(tuning.angle_thrust, Tuning::default().angle_thrust);
+
+        assert!(matches!(
+            Tuning::parse("(\nthrust: 1.0,\nspeed: 2.0,\n)"),
+            Err(RonError::Invalid { line: 3, .. })
+        ));
+        match Tuning::parse("(drag: -1.0)") {
+            Err(RonError::Invalid { message, .. }) => {
+                assert_eq!(message, "drag can't be negative or infinite")
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_file_matches_defaults() {
+        let tuning = Tuning::parse(include_str!("../../tuning.ron")).unwrap();
+        assert_eq!(tuning, Tuning::default());
+        let tuning = Tuning::load(Path::new("does/not/exist.ron")).unwrap();
+        assert_eq!(tuning, Tuning::default());
+    }
+}
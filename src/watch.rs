@@ -0,0 +1,69 @@
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+// Notices changes of a file by polling its modification time, good enough
+// for reloading a few settings files while the game runs.
+pub struct FileWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+impl FileWatcher {
+    pub fn new(path: &Path) -> Self {
+        FileWatcher {
+            path: path.to_path_buf(),
+            modified: modified(path),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // True once for every change since the last call, a file that appears
+    // or disappears counts as changed too.
+    pub fn changed(&mut self) -> bool {
+        let modified = modified(&self.path);
+        if modified != self.modified {
+            self.modified = modified;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_changed() {
+        let path = std::env::temp_dir().join(format!("blobber-watch-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut watcher = FileWatcher::new(&path);
+        assert!(!watcher.changed());
+
+        std::fs::write(&path, "first").unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+
+        // Set explicitly, file systems might not see two writes in a row as
+        // different times.
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(10))
+            .unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(watcher.changed());
+    }
+}
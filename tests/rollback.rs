@@ -0,0 +1,85 @@
+use blobber::net::rollback::RollbackSession;
+use blobber::net::transport::{LinkConditioner, UdpTransport};
+use blobber::simulation::blob::OwnerId;
+use blobber::simulation::{Action, InputEvent, Simulation};
+use std::collections::HashMap;
+use std::net::UdpSocket;
+use std::time::{Duration, Instant};
+
+fn actions(owner: OwnerId, frame: u64) -> Vec<Action> {
+    match (frame / 5 + owner as u64) % 4 {
+        0 => vec![Action::new(InputEvent::Forward, 1.0)],
+        1 => vec![
+            Action::new(InputEvent::Forward, 1.0),
+            Action::new(InputEvent::TurnRight, 1.0),
+        ],
+        2 => vec![Action::new(InputEvent::Backward, 0.5)],
+        _ => Vec::new(),
+    }
+}
+
+#[test]
+fn test_rollback_over_lossy_loopback() {
+    let dt = 1.0 / 60.0;
+    let frames = 120;
+    let latency = Duration::from_millis(20);
+    let loss = 0.2;
+
+    let first_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let second_socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let first_addr = first_socket.local_addr().unwrap();
+    let first_udp =
+        UdpTransport::from_socket(first_socket, second_socket.local_addr().unwrap()).unwrap();
+    let second_udp = UdpTransport::from_socket(second_socket, first_addr).unwrap();
+
+    let mut first_simulation = Simulation::new();
+    let mut second_simulation = Simulation::new();
+    let mut first = RollbackSession::new(
+        &mut first_simulation,
+        LinkConditioner::new(first_udp, latency, loss, 1),
+        0,
+        1,
+        dt,
+    );
+    let mut second = RollbackSession::new(
+        &mut second_simulation,
+        LinkConditioner::new(second_udp, latency, loss, 2),
+        1,
+        0,
+        dt,
+    );
+
+    let started = Instant::now();
+    while first.confirmed_frame() < frames || second.confirmed_frame() < frames {
+        assert!(started.elapsed() < Duration::from_secs(20), "peers stuck");
+
+        let frame = first_simulation.step();
+        if frame < frames {
+            first.advance(&mut first_simulation, &actions(0, frame));
+        } else {
+            first.idle(&mut first_simulation);
+        }
+        let frame = second_simulation.step();
+        if frame < frames {
+            second.advance(&mut second_simulation, &actions(1, frame));
+        } else {
+            second.idle(&mut second_simulation);
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    let mut reference = Simulation::new();
+    reference.set_deterministic(true);
+    for frame in 0..frames {
+        let mut inputs = HashMap::new();
+        inputs.insert(0, actions(0, frame));
+        inputs.insert(1, actions(1, frame));
+        reference.simulate(&inputs, dt);
+    }
+
+    assert!(first.rollbacks() > 0 || second.rollbacks() > 0);
+    assert_eq!(first_simulation.state_hash(), reference.state_hash());
+    assert_eq!(second_simulation.state_hash(), reference.state_hash());
+    assert_eq!(first.desync(), None);
+    assert_eq!(second.desync(), None);
+}
@@ -0,0 +1,155 @@
+use blobber::math::point::PointF32;
+use blobber::math::rect::Rect;
+use blobber::net::client::Client;
+use blobber::net::server::Server;
+use blobber::simulation::{Action, InputEvent, Simulation};
+use std::time::Duration;
+
+fn arena() -> Rect {
+    Rect::new(
+        PointF32::new(-1000.0, -1000.0),
+        PointF32::new(1000.0, 1000.0),
+    )
+}
+
+#[test]
+fn test_clients_play_on_local_server() {
+    let dt = 1.0 / 60.0;
+    let mut server = Server::bind("127.0.0.1:0", Simulation::new_empty(arena())).unwrap();
+    let addr = server.local_addr().unwrap();
+
+    // 'connect' waits for the welcome, which is sent from 'tick'.
+    let first = std::thread::spawn(move || Client::connect(addr).unwrap());
+    let second = std::thread::spawn(move || Client::connect(addr).unwrap());
+    while server.client_count() < 2 {
+        server.tick(dt);
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    let mut clients = vec![first.join().unwrap(), second.join().unwrap()];
+    assert_ne!(clients[0].owner(), clients[1].owner());
+    assert_eq!(clients[0].rect(), arena());
+
+    let mut mirrors = vec![
+        Simulation::new_empty(clients[0].rect()),
+        Simulation::new_empty(clients[1].rect()),
+    ];
+    let blob_id = server.simulation().owned_blobs(clients[0].owner())[0];
+    let start = server.simulation().find_blob(blob_id).unwrap().circle.pos;
+    for _ in 0..60 {
+        clients[0].send_inputs(&[Action::new(InputEvent::Forward, 1.0)]);
+        clients[1].send_inputs(&[]);
+        std::thread::sleep(Duration::from_millis(2));
+        server.tick(dt);
+        std::thread::sleep(Duration::from_millis(2));
+        for (client, mirror) in clients.iter_mut().zip(&mut mirrors) {
+            client.poll(mirror);
+        }
+    }
+
+    // Only the first client moved, and both see it where the server has it.
+    let server_blob = server.simulation().find_blob(blob_id).unwrap();
+    assert!(server_blob.circle.pos.x > start.x + 10.0);
+    for (client, mirror) in clients.iter().zip(&mirrors) {
+        assert_eq!(client.step(), Some(server.simulation().step()));
+        assert_eq!(mirror.blobs().len(), 2);
+        let blob = mirror.find_blob(server_blob.id).unwrap();
+        assert_eq!(blob.circle.pos, server_blob.circle.pos);
+        assert_eq!(blob.owner, clients[0].owner());
+    }
+
+    // When a client leaves its blobs are removed, for the others too.
+    let left = clients.remove(0);
+    let left_owner = left.owner();
+    drop(left);
+    for _ in 0..20 {
+        clients[0].send_inputs(&[]);
+        std::thread::sleep(Duration::from_millis(2));
+        server.tick(dt);
+        std::thread::sleep(Duration::from_millis(2));
+        clients[0].poll(&mut mirrors[1]);
+    }
+    assert_eq!(server.client_count(), 1);
+    assert!(server.simulation().owned_blobs(left_owner).is_empty());
+    assert!(mirrors[1].owned_blobs(left_owner).is_empty());
+    assert!(mirrors[1].take_events().is_empty());
+    assert!(clients[0].is_connected());
+}
+
+#[test]
+fn test_client_gets_health_and_respawns() {
+    let dt = 1.0 / 60.0;
+    let mut server = Server::bind("127.0.0.1:0", Simulation::new_empty(arena())).unwrap();
+    let addr = server.local_addr().unwrap();
+
+    let connecting = std::thread::spawn(move || Client::connect(addr).unwrap());
+    while server.client_count() < 1 {
+        server.tick(dt);
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    let mut client = connecting.join().unwrap();
+    let mut mirror = Simulation::new_empty(client.rect());
+    let play = |server: &mut Server, client: &mut Client, mirror: &mut Simulation| {
+        for _ in 0..10 {
+            client.send_inputs(&[]);
+            std::thread::sleep(Duration::from_millis(2));
+            server.tick(dt);
+            std::thread::sleep(Duration::from_millis(2));
+            client.poll(mirror);
+        }
+    };
+
+    let blob_id = server.simulation().owned_blobs(client.owner())[0];
+    server
+        .simulation_mut()
+        .find_blob_mut(blob_id)
+        .unwrap()
+        .health = 30.0;
+    play(&mut server, &mut client, &mut mirror);
+    assert_eq!(mirror.find_blob(blob_id).unwrap().health, 30.0);
+
+    // Once all its blobs are gone the client gets a new one.
+    server.simulation_mut().despawn(blob_id);
+    play(&mut server, &mut client, &mut mirror);
+    assert_eq!(server.client_count(), 1);
+    let respawned = server.simulation().owned_blobs(client.owner());
+    assert_eq!(respawned.len(), 1);
+    assert_ne!(respawned[0], blob_id);
+    assert!(mirror.find_blob(blob_id).is_none());
+    let blob = mirror.find_blob(respawned[0]).unwrap();
+    assert_eq!(blob.health, blob.max_health);
+}
+
+#[test]
+fn test_client_sees_projectiles() {
+    let dt = 1.0 / 60.0;
+    let mut server = Server::bind("127.0.0.1:0", Simulation::new_empty(arena())).unwrap();
+    let addr = server.local_addr().unwrap();
+
+    let connecting = std::thread::spawn(move || Client::connect(addr).unwrap());
+    while server.client_count() < 1 {
+        server.tick(dt);
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    let mut client = connecting.join().unwrap();
+    let mut mirror = Simulation::new_empty(client.rect());
+
+    for i in 0..10 {
+        let actions = if i == 0 {
+            vec![Action::new(InputEvent::Fire, 1.0)]
+        } else {
+            Vec::new()
+        };
+        client.send_inputs(&actions);
+        std::thread::sleep(Duration::from_millis(2));
+        server.tick(dt);
+        std::thread::sleep(Duration::from_millis(2));
+        client.poll(&mut mirror);
+    }
+
+    assert_eq!(client.step(), Some(server.simulation().step()));
+    assert_eq!(server.simulation().projectiles().len(), 1);
+    assert_eq!(mirror.projectiles().len(), 1);
+    let projectile = &server.simulation().projectiles()[0];
+    assert_eq!(mirror.projectiles()[0].circle.pos, projectile.circle.pos);
+    assert_eq!(mirror.projectiles()[0].owner, client.owner());
+}
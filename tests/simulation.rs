@@ -0,0 +1,54 @@
+use blobber::math::point::PointF32;
+use blobber::math::rect::Rect;
+use blobber::simulation::blob::BlobDesc;
+use blobber::simulation::{Action, Event, InputEvent, Simulation};
+use std::collections::HashMap;
+
+fn arena() -> Rect {
+    Rect::new(
+        PointF32::new(-1000.0, -1000.0),
+        PointF32::new(1000.0, 1000.0),
+    )
+}
+
+#[test]
+fn test_blobs_driven_into_each_other_collide() {
+    let mut simulation = Simulation::new_empty(arena());
+    let first = simulation.spawn_blob(&BlobDesc::new(0.0, 0.0, 10.0));
+    let mut desc = BlobDesc::new(100.0, 0.0, 10.0);
+    desc.rotation = std::f32::consts::PI;
+    let second = simulation.spawn_blob(&desc);
+
+    let mut inputs = HashMap::new();
+    inputs.insert(first, vec![Action::new(InputEvent::Forward, 1.0)]);
+    inputs.insert(second, vec![Action::new(InputEvent::Forward, 1.0)]);
+
+    let mut collided = false;
+    for _ in 0..120 {
+        let events = simulation.simulate(&inputs, 1.0 / 60.0);
+        collided |= events.iter().any(|event| match event {
+            Event::Collision((a, b, _, _)) => (*a, *b) == (first, second),
+            _ => false,
+        });
+    }
+
+    assert!(collided);
+    let first = simulation.find_blob(first).unwrap();
+    let second = simulation.find_blob(second).unwrap();
+    assert!(first.circle.pos.x < second.circle.pos.x);
+}
+
+#[test]
+fn test_blobs_stay_inside_arena() {
+    let mut simulation = Simulation::new_empty(arena());
+    let blob_id = simulation.spawn_blob(&BlobDesc::new(900.0, 0.0, 10.0));
+
+    let mut inputs = HashMap::new();
+    inputs.insert(blob_id, vec![Action::new(InputEvent::Forward, 1.0)]);
+
+    for _ in 0..600 {
+        simulation.simulate(&inputs, 1.0 / 60.0);
+        let blob = simulation.find_blob(blob_id).unwrap();
+        assert!(blob.circle.pos.x + blob.circle.radius <= 1000.0 + 0.001);
+    }
+}